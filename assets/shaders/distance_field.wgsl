@@ -1,5 +1,7 @@
-@group(0) @binding(0) var tex_jfa: texture_2d<u32>;
-@group(0) @binding(1) var tex_dist_field: texture_storage_2d<r16float, write>;
+@group(0) @binding(0) var tex_jfa: texture_2d<i32>;
+@group(0) @binding(1) var tex_jfa_static: texture_2d<i32>;
+@group(0) @binding(2) var tex_coverage: texture_2d<f32>;
+@group(0) @binding(3) var tex_dist_field: texture_storage_2d<r16float, write>;
 
 @compute
 @workgroup_size(8, 8, 1)
@@ -10,11 +12,31 @@ fn distance_field(
     let base_coordinates = vec2<u32>(global_id.xy);
     let base_coordinatesf = vec2<f32>(base_coordinates);
 
-    let jfa = vec2<f32>(textureLoad(tex_jfa, base_coordinates, 0).rg);
+    let jfa = textureLoad(tex_jfa, base_coordinates, 0).rg;
+    let jfa_static = textureLoad(tex_jfa_static, base_coordinates, 0).rg;
+
+    // Combine dynamic and cached static occluders
+    let dist = min(
+        seed_distance(base_coordinatesf, jfa),
+        seed_distance(base_coordinatesf, jfa_static)
+    );
 
     textureStore(
         tex_dist_field,
         base_coordinates,
-        vec4<f32>(distance(base_coordinatesf, jfa))
+        vec4<f32>(dist)
     );
 }
+
+// Distance to a seed, pushed back by the part of the seed's pixel its occluder leaves uncovered.
+fn seed_distance(coordinates: vec2<f32>, seed: vec2<i32>) -> f32 {
+    let dist = distance(coordinates, vec2<f32>(seed));
+    // Partially covered pixels still occlude themselves, far and scrolled out seeds lie outside
+    // of the texture
+    let dimensions = vec2<i32>(textureDimensions(tex_coverage));
+    if (dist == 0.0 || any(seed < vec2<i32>(0)) || any(seed >= dimensions)) {
+        return dist;
+    }
+
+    return dist + 1.0 - textureLoad(tex_coverage, seed, 0).r;
+}
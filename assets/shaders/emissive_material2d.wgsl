@@ -0,0 +1,29 @@
+#import bevy_sprite::{
+    mesh2d_vertex_output::VertexOutput,
+    mesh2d_view_bindings::view,
+}
+#import "shaders/emissive_material2d_types.wgsl"::{EmissiveMaterial2d, EMISSIVE_MATERIAL_TEXTURE_BIT};
+
+#ifdef TONEMAP_IN_SHADER
+#import bevy_core_pipeline::tonemapping
+#endif
+
+@group(2) @binding(0) var<uniform> material: EmissiveMaterial2d;
+@group(2) @binding(1) var texture: texture_2d<f32>;
+@group(2) @binding(2) var texture_sampler: sampler;
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    // Emission is left to the cascades
+    var output_color = material.color;
+#ifdef VERTEX_COLORS
+    output_color = output_color * mesh.color;
+#endif
+    if ((material.flags & EMISSIVE_MATERIAL_TEXTURE_BIT) != 0u) {
+        output_color = output_color * textureSample(texture, texture_sampler, mesh.uv);
+    }
+#ifdef TONEMAP_IN_SHADER
+    output_color = tonemapping::tone_mapping(output_color, view.color_grading);
+#endif
+    return output_color;
+}
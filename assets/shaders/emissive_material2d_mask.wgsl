@@ -0,0 +1,38 @@
+#import bevy_sprite::mesh2d_view_bindings::globals
+#import "shaders/mask2d_types.wgsl"::{Mask2dVertexOutput, Mask2dOutput, mask2d_output};
+#import "shaders/emissive_material2d_types.wgsl"::{
+    EmissiveMaterial2d,
+    EMISSIVE_MATERIAL_TEXTURE_BIT,
+    EMISSIVE_MATERIAL_EMISSION_TEXTURE_BIT,
+    emission_curve,
+};
+
+@group(2) @binding(0) var<uniform> material: EmissiveMaterial2d;
+@group(2) @binding(1) var texture: texture_2d<f32>;
+@group(2) @binding(2) var texture_sampler: sampler;
+@group(2) @binding(3) var emission_texture: texture_2d<f32>;
+@group(2) @binding(4) var emission_sampler: sampler;
+
+#ifdef MASK2D_ALPHA_CUTOFF
+const ALPHA_CUTOFF: f32 = f32(#{MASK2D_ALPHA_CUTOFF}u) / 255.0;
+#endif
+
+@fragment
+fn fragment(in: Mask2dVertexOutput) -> Mask2dOutput {
+#ifdef MASK2D_ALPHA_CUTOFF
+    // Transparent texels do not occlude
+    if ((material.flags & EMISSIVE_MATERIAL_TEXTURE_BIT) != 0u) {
+        if textureSample(texture, texture_sampler, in.uv).a < ALPHA_CUTOFF {
+            discard;
+        }
+    }
+#endif
+
+    var emission = material.emission * emission_curve(material, globals.time);
+    if ((material.flags & EMISSIVE_MATERIAL_EMISSION_TEXTURE_BIT) != 0u) {
+        let uv = in.uv + material.emission_scroll * globals.time;
+        emission *= textureSample(emission_texture, emission_sampler, uv).rgb;
+    }
+
+    return mask2d_output(in.object_index, emission, 1.0);
+}
@@ -0,0 +1,39 @@
+struct EmissiveMaterial2d {
+    color: vec4<f32>,
+    emission: vec3<f32>,
+    flags: u32,
+    emission_scroll: vec2<f32>,
+    curve_len: u32,
+    // (time, multiplier, _, _)
+    curve: array<vec4<f32>, 8>,
+}
+
+const EMISSIVE_MATERIAL_TEXTURE_BIT: u32 = 1u;
+const EMISSIVE_MATERIAL_EMISSION_TEXTURE_BIT: u32 = 2u;
+
+// Emission multiplier at the given time, looping over the last key.
+fn emission_curve(material: EmissiveMaterial2d, time: f32) -> f32 {
+    if (material.curve_len == 0u) {
+        return 1.0;
+    }
+
+    // Arrays can only be indexed dynamically through a variable
+    var curve = material.curve;
+    let duration = curve[material.curve_len - 1u].x;
+    let t = select(0.0, time % duration, duration > 0.0);
+
+    var prev = curve[0];
+    if (t <= prev.x) {
+        return prev.y;
+    }
+
+    for (var k = 1u; k < material.curve_len; k++) {
+        let next = curve[k];
+        if (t <= next.x) {
+            return mix(prev.y, next.y, (t - prev.x) / max(next.x - prev.x, 1e-6));
+        }
+        prev = next;
+    }
+
+    return prev.y;
+}
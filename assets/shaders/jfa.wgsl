@@ -1,6 +1,6 @@
 @group(0) @binding(0) var<uniform> step_size: i32;
-@group(0) @binding(1) var tex_jfa_source: texture_2d<u32>;
-@group(0) @binding(2) var tex_jfa_destination: texture_storage_2d<rg16uint, write>;
+@group(0) @binding(1) var tex_jfa_source: texture_2d<i32>;
+@group(0) @binding(2) var tex_jfa_destination: texture_storage_2d<rg16sint, write>;
 
 const OFFSET_COUNT = 8;
 
@@ -53,6 +53,6 @@ fn jfa(
     textureStore(
         tex_jfa_destination,
         base_coord,
-        vec4<u32>(best_coord, 0, 0)
+        vec4<i32>(best_coord, 0, 0)
     );
 }
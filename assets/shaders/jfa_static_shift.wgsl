@@ -0,0 +1,35 @@
+@group(0) @binding(0) var<uniform> offset: vec2<i32>;
+@group(0) @binding(1) var tex_static_mask: texture_2d<u32>;
+@group(0) @binding(2) var tex_jfa_cache: texture_2d<i32>;
+@group(0) @binding(3) var tex_jfa: texture_storage_2d<rg16sint, write>;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn jfa_static_shift(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let base_coord = vec2<i32>(global_id.xy);
+    let dimensions = vec2<i32>(textureDimensions(tex_jfa));
+
+    if any(base_coord >= dimensions) {
+        return;
+    }
+
+    // Set to a far distance
+    let far_coordinate = dimensions * 3;
+    var seed = far_coordinate;
+
+    if textureLoad(tex_static_mask, base_coord, 0).r != 0u {
+        seed = base_coord;
+    } else {
+        // Newly exposed pixels are left for the flood to fill in
+        let source_coord = base_coord - offset;
+        if all(source_coord >= vec2<i32>(0)) && all(source_coord < dimensions) {
+            let cached_seed = textureLoad(tex_jfa_cache, source_coord, 0).rg;
+            // Seeds scrolled out of view are kept, they still occlude the pixels near the edge
+            if any(cached_seed != far_coordinate) {
+                seed = cached_seed + offset;
+            }
+        }
+    }
+
+    textureStore(tex_jfa, base_coord, vec4<i32>(seed, 0, 0));
+}
@@ -0,0 +1,205 @@
+#import bevy_render::maths::PI_2
+
+const LIGHT2D_POINT: u32 = 0u;
+const LIGHT2D_SPOT: u32 = 1u;
+const LIGHT2D_LINE: u32 = 2u;
+
+const MAX_SHADOW_MARCH: u32 = 64;
+const SHADOW_EPSILON: f32 = 4.88e-04;
+
+struct Light2d {
+    // Color multiplied by intensity
+    color: vec3<f32>,
+    kind: u32,
+    // Center of point and spot lights, start of line lights
+    position: vec2<f32>,
+    // Direction of spot lights, end of line lights
+    direction: vec2<f32>,
+    radius: f32,
+    falloff: f32,
+    cos_angle: f32,
+    // Evaluated per pixel instead of by the cascades
+    direct: u32,
+    shadow_sharpness: f32,
+}
+
+// Distance along the ray to the first intersection with the light, zero if starting inside and
+// negative if missed.
+fn light2d_intersect(light: Light2d, origin: vec2<f32>, ray_dir: vec2<f32>) -> f32 {
+    if (light.kind == LIGHT2D_LINE) {
+        return capsule_intersect(origin, ray_dir, light.position, light.direction, light.radius);
+    }
+
+    return circle_intersect(origin, ray_dir, light.position, light.radius);
+}
+
+// Radiance of the light towards a ray that hit it after travelling `dist`.
+fn light2d_radiance(light: Light2d, ray_dir: vec2<f32>, dist: f32) -> vec3<f32> {
+    var radiance = light.color / (1.0 + light.falloff * dist);
+
+    if (light.kind == LIGHT2D_SPOT) {
+        // Only emit towards rays coming from inside the cone
+        radiance *= f32(dot(-ray_dir, light.direction) >= light.cos_angle);
+    }
+
+    return radiance;
+}
+
+// Closest point of the light's emitter to a pixel, used as the target of its shadow ray.
+fn light2d_closest_point(light: Light2d, pixel: vec2<f32>) -> vec2<f32> {
+    if (light.kind == LIGHT2D_LINE) {
+        let ba = light.direction - light.position;
+        let h = saturate(dot(pixel - light.position, ba) / max(dot(ba, ba), 1e-6));
+        return light.position + ba * h;
+    }
+
+    return light.position;
+}
+
+// Unshadowed radiance received by a pixel, averaged over all directions like the cascades do.
+fn light2d_irradiance(light: Light2d, pixel: vec2<f32>) -> vec3<f32> {
+    let closest = light2d_closest_point(light, pixel);
+    let dist = distance(pixel, closest);
+    // Angular size of the emitter as seen from the pixel
+    var angle = 2.0 * asin(min(light.radius / max(dist, 1e-6), 1.0));
+
+    if (light.kind == LIGHT2D_LINE) {
+        angle += acos(clamp(dot(
+            normalize(light.position - pixel),
+            normalize(light.direction - pixel)
+        ), -1.0, 1.0));
+    }
+
+    // Rays towards the light point from the pixel back to it
+    let ray_dir = normalize(closest - pixel);
+    return light2d_radiance(light, ray_dir, dist) * min(angle / PI_2, 1.0);
+}
+
+// Irradiance of a `DirectLight2d` at a pixel, shadowed through the distance field.
+fn light2d_direct_irradiance(
+    light: Light2d,
+    tex_dist_field: texture_2d<f32>,
+    pixel: vec2<f32>,
+) -> vec3<f32> {
+    let irradiance = light2d_irradiance(light, pixel);
+    if (all(irradiance <= vec3<f32>(0.0))) {
+        return vec3<f32>(0.0);
+    }
+
+    return irradiance * soft_shadow(
+        tex_dist_field,
+        pixel,
+        light2d_closest_point(light, pixel),
+        light.radius,
+        light.shadow_sharpness
+    );
+}
+
+// Soft shadow traced through the distance field towards the light's emitter.
+fn soft_shadow(
+    tex_dist_field: texture_2d<f32>,
+    origin: vec2<f32>,
+    emitter: vec2<f32>,
+    radius: f32,
+    sharpness: f32,
+) -> f32 {
+    let to_emitter = emitter - origin;
+    // Stop at the surface of the emitter
+    let max_dist = length(to_emitter) - radius;
+    if (max_dist <= 0.0) {
+        return 1.0;
+    }
+
+    let ray_dir = normalize(to_emitter);
+    let dimensions = vec2<f32>(textureDimensions(tex_dist_field));
+
+    var shadow = 1.0;
+    var t = 0.0;
+    for (var r = 0u; r < MAX_SHADOW_MARCH; r++) {
+        let position = origin + ray_dir * t;
+        if (t >= max_dist || any(position >= dimensions) || any(position < vec2<f32>(0.0))) {
+            break;
+        }
+
+        let dist = textureLoad(tex_dist_field, vec2<u32>(position), 0).r;
+        if (dist < SHADOW_EPSILON) {
+            return 0.0;
+        }
+
+        // Penumbra from how closely the ray passes an occluder
+        shadow = min(shadow, sharpness * dist / max(t, 1.0));
+        t += dist;
+    }
+
+    return saturate(shadow);
+}
+
+fn circle_intersect(origin: vec2<f32>, ray_dir: vec2<f32>, center: vec2<f32>, radius: f32) -> f32 {
+    let oc = origin - center;
+    let c = dot(oc, oc) - radius * radius;
+    // Starting inside
+    if (c <= 0.0) {
+        return 0.0;
+    }
+
+    let b = dot(oc, ray_dir);
+    let h = b * b - c;
+    if (h < 0.0) {
+        return -1.0;
+    }
+
+    return -b - sqrt(h);
+}
+
+fn capsule_intersect(
+    origin: vec2<f32>,
+    ray_dir: vec2<f32>,
+    start: vec2<f32>,
+    end: vec2<f32>,
+    radius: f32,
+) -> f32 {
+    let ba = end - start;
+    let oa = origin - start;
+
+    let baba = dot(ba, ba);
+    // Starting inside
+    let closest = start + ba * saturate(dot(oa, ba) / max(baba, 1e-6));
+    if (distance(origin, closest) <= radius) {
+        return 0.0;
+    }
+
+    let bard = dot(ba, ray_dir);
+    let baoa = dot(ba, oa);
+    let rdoa = dot(ray_dir, oa);
+    let oaoa = dot(oa, oa);
+
+    let a = baba - bard * bard;
+    let b = baba * rdoa - baoa * bard;
+    let c = baba * oaoa - baoa * baoa - radius * radius * baba;
+    let h = b * b - a * c;
+    if (h >= 0.0) {
+        // Rays parallel to the segment can only enter through the cap facing them
+        var y = select(baba, 0.0, bard > 0.0);
+        if (abs(a) > 1e-6 * baba) {
+            // Body of the capsule
+            let t = (-b - sqrt(h)) / a;
+            y = baoa + t * bard;
+            if (y > 0.0 && y < baba) {
+                return t;
+            }
+        }
+
+        // Caps of the capsule
+        var oc = origin - end;
+        if (y <= 0.0) {
+            oc = oa;
+        }
+        let cap_b = dot(ray_dir, oc);
+        let cap_h = cap_b * cap_b - dot(oc, oc) + radius * radius;
+        if (cap_h > 0.0) {
+            return -cap_b - sqrt(cap_h);
+        }
+    }
+
+    return -1.0;
+}
@@ -1,6 +1,23 @@
-#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+#import "shaders/mask2d_types.wgsl"::{Mask2dVertexOutput, Mask2dOutput, mask2d_output};
+
+#ifdef MASK2D_ALPHA_CUTOFF
+@group(2) @binding(1) var texture: texture_2d<f32>;
+@group(2) @binding(2) var texture_sampler: sampler;
+
+const ALPHA_CUTOFF: f32 = f32(#{MASK2D_ALPHA_CUTOFF}u) / 255.0;
+#endif
 
 @fragment
-fn fragment(in: VertexOutput) -> @location(0) u32 {
-    return 1u;
+fn fragment(in: Mask2dVertexOutput) -> Mask2dOutput {
+#ifdef MASK2D_ALPHA_CUTOFF
+#ifdef VERTEX_UVS
+    // Transparent texels do not occlude
+    if textureSample(texture, texture_sampler, in.uv).a < ALPHA_CUTOFF {
+        discard;
+    }
+#endif
+#endif
+
+    // Lighting comes from the object's Mask2dProperties
+    return mask2d_output(in.object_index, vec3<f32>(0.0), 1.0);
 }
@@ -0,0 +1,60 @@
+#import bevy_sprite::{
+    mesh2d_bindings::mesh,
+    mesh2d_functions as mesh_functions,
+}
+#import "shaders/mask2d_types.wgsl"::Mask2dVertexOutput;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+#ifdef VERTEX_POSITIONS
+    @location(0) position: vec3<f32>,
+#endif
+#ifdef VERTEX_NORMALS
+    @location(1) normal: vec3<f32>,
+#endif
+#ifdef VERTEX_UVS
+    @location(2) uv: vec2<f32>,
+#endif
+#ifdef VERTEX_TANGENTS
+    @location(3) tangent: vec4<f32>,
+#endif
+#ifdef VERTEX_COLORS
+    @location(4) color: vec4<f32>,
+#endif
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> Mask2dVertexOutput {
+    var out: Mask2dVertexOutput;
+#ifdef VERTEX_UVS
+    out.uv = vertex.uv;
+#endif
+
+#ifdef VERTEX_POSITIONS
+    var world_from_local = mesh_functions::get_world_from_local(vertex.instance_index);
+    out.world_position = mesh_functions::mesh2d_position_local_to_world(
+        world_from_local,
+        vec4<f32>(vertex.position, 1.0)
+    );
+    out.position = mesh_functions::mesh2d_position_world_to_clip(out.world_position);
+#endif
+
+#ifdef VERTEX_NORMALS
+    out.world_normal = mesh_functions::mesh2d_normal_local_to_world(vertex.normal, vertex.instance_index);
+#endif
+
+#ifdef VERTEX_TANGENTS
+    out.world_tangent = mesh_functions::mesh2d_tangent_local_to_world(
+        world_from_local,
+        vertex.tangent
+    );
+#endif
+
+#ifdef VERTEX_COLORS
+    out.color = vertex.color;
+#endif
+
+    // Object index is stored in the flags of the mask's own mesh uniforms
+    out.object_index = mesh[vertex.instance_index].flags;
+    return out;
+}
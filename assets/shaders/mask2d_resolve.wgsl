@@ -0,0 +1,38 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+@group(0) @binding(0) var tex_mask: texture_multisampled_2d<u32>;
+@group(0) @binding(1) var tex_static_mask: texture_multisampled_2d<u32>;
+
+struct Mask2dResolveOutput {
+    @location(0) mask: u32,
+    @location(1) static_mask: u32,
+    // Fraction of the samples covered by any occluder
+    @location(2) coverage: f32,
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> Mask2dResolveOutput {
+    let coord = vec2<u32>(in.position.xy);
+    let sample_count = textureNumSamples(tex_mask);
+
+    var out = Mask2dResolveOutput(0u, 0u, 0.0);
+    var covered = 0u;
+    for (var s = 0u; s < sample_count; s++) {
+        let mask = textureLoad(tex_mask, coord, i32(s)).r;
+        let static_mask = textureLoad(tex_static_mask, coord, i32(s)).r;
+
+        // Any covered sample keeps the object in the mask, so that thin edges are not lost
+        if (out.mask == 0u) {
+            out.mask = mask;
+        }
+        if (out.static_mask == 0u) {
+            out.static_mask = static_mask;
+        }
+        if (mask != 0u || static_mask != 0u) {
+            covered += 1u;
+        }
+    }
+
+    out.coverage = f32(covered) / f32(sample_count);
+    return out;
+}
@@ -0,0 +1,61 @@
+#import bevy_sprite::mesh2d_view_bindings::view
+#import "shaders/mask2d_types.wgsl"::{Mask2dOutput, mask2d_output};
+
+struct SpriteMask2dInstance {
+    world_from_local: mat4x4<f32>,
+    uv_offset_scale: vec4<f32>,
+    alpha_cutoff: f32,
+    object_index: u32,
+}
+
+@group(1) @binding(0) var<storage, read> instances: array<SpriteMask2dInstance>;
+@group(2) @binding(0) var sprite_texture: texture_2d<f32>;
+@group(2) @binding(1) var sprite_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) alpha_cutoff: f32,
+    @location(2) @interpolate(flat) object_index: u32,
+}
+
+@vertex
+fn vertex(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var quad = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+
+    let instance = instances[instance_index];
+    let vertex_position = quad[vertex_index];
+
+    var out: VertexOutput;
+    out.clip_position = view.clip_from_world
+        * instance.world_from_local
+        * vec4<f32>(vertex_position, 0.0, 1.0);
+    out.uv = vertex_position * instance.uv_offset_scale.zw + instance.uv_offset_scale.xy;
+    out.alpha_cutoff = instance.alpha_cutoff;
+    out.object_index = instance.object_index;
+
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> Mask2dOutput {
+    let alpha = textureSample(sprite_texture, sprite_sampler, in.uv).a;
+
+    // Transparent texels do not occlude
+    if alpha < in.alpha_cutoff {
+        discard;
+    }
+
+    // Lighting comes from the object's Mask2dProperties
+    return mask2d_output(in.object_index, vec3<f32>(0.0), 1.0);
+}
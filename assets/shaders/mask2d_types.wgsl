@@ -0,0 +1,37 @@
+struct Mask2dVertexOutput {
+    // Matches bevy_sprite::mesh2d_vertex_output::VertexOutput
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+#ifdef VERTEX_TANGENTS
+    @location(3) world_tangent: vec4<f32>,
+#endif
+#ifdef VERTEX_COLORS
+    @location(4) color: vec4<f32>,
+#endif
+    // Index into the Mask2dProperties table
+    @location(5) @interpolate(flat) object_index: u32,
+}
+
+struct Mask2dOutput {
+    // Dynamic occluders
+    @location(0) mask: u32,
+    // Static occluders
+    @location(1) static_mask: u32,
+    // Emission (rgb) and opacity (a)
+    @location(2) emission: vec4<f32>,
+}
+
+struct Mask2dProperties {
+    emission: vec3<f32>,
+    opacity: f32,
+    albedo: vec3<f32>,
+    absorption: f32,
+    transmission: vec3<f32>,
+}
+
+// The pipeline's write masks decide which mask layer receives the object index.
+fn mask2d_output(object_index: u32, emission: vec3<f32>, opacity: f32) -> Mask2dOutput {
+    return Mask2dOutput(object_index, object_index, vec4<f32>(emission, opacity));
+}
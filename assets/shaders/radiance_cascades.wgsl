@@ -1,15 +1,54 @@
-#import bevy_render::maths::{PI_2, HALF_PI}
-#import "shaders/radiance_probe.wgsl"::Probe;
+#import bevy_render::maths::HALF_PI
+#import "shaders/radiance_probe.wgsl"::{
+    Probe, EMISSION_MAIN, EMISSION_NEGATIVE, ray_angle, ray_jitter
+};
+#import "shaders/mask2d_types.wgsl"::Mask2dProperties;
+#import "shaders/light2d.wgsl"::{
+    Light2d, light2d_direct_irradiance, light2d_intersect, light2d_radiance
+};
 
 const QUARTER_PI: f32 = HALF_PI * 0.5;
 const MAX_RAYMARCH: u32 = 32;
+// Conservative marches take texel sized steps near occluders, up to one per texel of the interval
+const MAX_CONSERVATIVE_RAYMARCH: u32 = 128;
+const MAX_CONSERVATIVE_RAYMARCH_CAP: u32 = 1024;
+// Distance values are sampled at texel centers and measured to occluder texel centers
+const CONSERVATIVE_MARGIN: f32 = 1.4142135;
 const EPSILON: f32 = 4.88e-04;
+// Filtered distances are measured to the edge of occluder texels, which converge slower
+const FILTERED_EPSILON: f32 = 0.05;
+// Fraction of the interval start that jittered intervals are shifted by at most
+const JITTER_START: f32 = 0.25;
+// Below this transmittance in every channel, the ray is considered blocked
+const MIN_TRANSMITTANCE: f32 = 1e-3;
+// Object index of an empty texel
+const NO_OBJECT: u32 = 0xffffffffu;
 
 @group(0) @binding(0) var<uniform> probe: Probe;
 @group(0) @binding(1) var tex_main: texture_2d<f32>;
-@group(0) @binding(2) var tex_dist_field: texture_2d<f32>;
-@group(0) @binding(3) var tex_radiance_cascades_source: texture_2d<f32>;
-@group(0) @binding(4) var tex_radiance_cascades_destination: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2) var tex_emission: texture_2d<f32>;
+@group(0) @binding(3) var tex_mask: texture_2d<u32>;
+@group(0) @binding(4) var tex_static_mask: texture_2d<u32>;
+@group(0) @binding(5) var<storage, read> mask2d_properties: array<Mask2dProperties>;
+@group(0) @binding(6) var<storage, read> lights: array<Light2d>;
+@group(0) @binding(7) var<uniform> fog: Fog2d;
+@group(0) @binding(8) var tex_fog_density: texture_2d<f32>;
+@group(0) @binding(9) var sampler_fog_density: sampler;
+@group(0) @binding(10) var tex_dist_field: texture_2d<f32>;
+@group(0) @binding(11) var sampler_dist_field: sampler;
+@group(0) @binding(12) var tex_radiance_cascades_source: texture_2d<f32>;
+@group(0) @binding(13) var tex_radiance_cascades_destination: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(14) var tex_blue_noise: texture_2d<f32>;
+@group(0) @binding(15) var tex_radiance_history: texture_2d<f32>;
+@group(0) @binding(16) var sampler_radiance_history: sampler;
+
+struct Fog2d {
+    ambient: vec3<f32>,
+    density: f32,
+    region_min: vec2<f32>,
+    region_size: vec2<f32>,
+    region_density: f32,
+}
 
 @compute
 @workgroup_size(8, 8, 1)
@@ -28,26 +67,34 @@ fn radiance_cascades(
     let probe_texel = base_coord % probe.width;
 
     let ray_index = probe_texel.x + probe_texel.y * probe.width;
-    let ray_count = probe.width * probe.width;
-
-    var ray_angle = (f32(ray_index) + 0.5) / f32(ray_count) * PI_2;
-    let ray_dir = normalize(vec2<f32>(cos(ray_angle), sin(ray_angle)));
 
     // Coordinate of cell in probe grid
     let probe_cell = base_coord / probe.width;
     // Start coordinate of the probe grid (in texture space)
     let probe_coord = probe_cell * probe.width;
 
+    let jitter = ray_jitter(probe, tex_blue_noise, probe_cell);
+    let angle = ray_angle(probe, ray_index, jitter.x);
+    let ray_dir = normalize(vec2<f32>(cos(angle), sin(angle)));
+
     // Center coordinate of the probe grid
     var probe_coord_center = probe_coord + probe.width / 2;
-    let origin = vec2<f32>(probe_coord_center) + ray_dir * probe.start;
+    let start = probe.start * (1.0 + jitter.y * JITTER_START);
+    let origin = vec2<f32>(probe_coord_center) + ray_dir * start;
 
-    var color = raymarch(origin, ray_dir, probe.range);
+    // Only the start is jittered, the interval still ends where the next cascade starts
+    let interval = raymarch(origin, ray_dir, start, probe.start + probe.range - start);
+    // Alpha keeps the average opacity of the interval
+    var color = vec4<f32>(interval.radiance, 1.0 - dot(interval.transmittance, vec3<f32>(1.0 / 3.0)));
 
 #ifdef MERGE
-    // TODO: Factor in transparency.
-    if (color.a != 1.0) {
-        color += merge(probe_cell, probe_coord, ray_index);
+    // Radiance from further cascades is tinted by the occluder's transmittance
+    if (any(interval.transmittance > vec3<f32>(0.0))) {
+        let merged = merge(probe_cell, probe_coord, ray_index);
+        color = vec4<f32>(
+            color.rgb + merged.rgb * interval.transmittance,
+            color.a + merged.a * (1.0 - color.a)
+        );
     }
 #endif
 
@@ -58,48 +105,265 @@ fn radiance_cascades(
     );
 }
 
-fn raymarch(origin: vec2<f32>, ray_dir: vec2<f32>, range: f32) -> vec4<f32> {
-    var color = vec4<f32>(0.0);
+struct RayInterval {
+    radiance: vec3<f32>,
+    // Per-channel fraction of the light behind the interval that passes through it
+    transmittance: vec3<f32>,
+}
+
+fn raymarch(origin: vec2<f32>, ray_dir: vec2<f32>, start: f32, range: f32) -> RayInterval {
+    // Light reaching the origin, attenuated by everything in front of where it was picked up
+    var color = vec3<f32>(0.0);
+    // Fraction of the light behind the march that passes through fog and occluders crossed so far
+    var transmittance = vec3<f32>(1.0);
     var position = origin;
     var covered_range = 0.0;
+    var density = 0.0;
+    // Distance to the closest occluder at the last sample
+    var dist = 0.0;
+    // Object of the occluder the march is inside of, crossing it only attenuates once
+    var inside_object = NO_OBJECT;
 
     let dimensions = vec2<f32>(textureDimensions(tex_main));
+    let conservative = probe.conservative != 0u;
+    let filtered = probe.filtered != 0u;
+    let epsilon = select(EPSILON, FILTERED_EPSILON, filtered);
+    let conservative_raymarch = clamp(
+        u32(ceil(range)),
+        MAX_CONSERVATIVE_RAYMARCH,
+        MAX_CONSERVATIVE_RAYMARCH_CAP
+    );
+    let max_raymarch = select(MAX_RAYMARCH, conservative_raymarch, conservative);
 
-    for (var r = 0u; r < MAX_RAYMARCH; r++) {
+    // Lights are opaque, nothing behind the closest one is marched
+    let light_hit = lights_hit(origin, ray_dir, start, range);
+
+    var r = 0u;
+    for (; r < max_raymarch; r++) {
         if (
-            covered_range >= range ||
+            covered_range >= light_hit.dist ||
             any(position >= dimensions) ||
             any(position < vec2<f32>(0.0))
         ) {
             break;
         }
 
-        let coord = vec2<u32>(round(position));
-        var dist = textureLoad(tex_dist_field, coord, 0).r;
+        var coord = vec2<u32>(round(position));
+        dist = sample_dist_field(position, coord, dimensions);
 
-        if (dist < EPSILON) {
-            // position += ray_dir * 1.5;
-            color = textureLoad(tex_main, coord, 0);
+        if (dist >= epsilon) {
+            inside_object = NO_OBJECT;
 
-            // Treat values from -1.0 ~ 1.0 as no light
-            // This way, we can handle both negative and postive light
-            let color_sign = sign(color);
-            let color_abs = abs(color);
+            var step = dist;
+            if (conservative) {
+                step = conservative_step(position, ray_dir, dist);
+            }
 
-            color.r = color_sign.r * max(color_abs.r - 1.0, 0.0);
-            color.g = color_sign.g * max(color_abs.g - 1.0, 0.0);
-            color.b = color_sign.b * max(color_abs.b - 1.0, 0.0);
+            // Beer-Lambert over the step, sampled at its start
+            density = fog_density(position);
+            let extinction = exp(-density * min(step, light_hit.dist - covered_range));
+            color += fog.ambient * transmittance * (1.0 - extinction);
+            transmittance *= extinction;
 
-            // TODO: Factor in transparency.
-            color.a = 1.0;
-            break;
+            position += ray_dir * step;
+            covered_range += step;
+            continue;
+        }
+
+        if (filtered) {
+            // Refine the hit onto the surface, then read the occluder texel behind it
+            let refinement = max(dist, 0.0);
+            position += ray_dir * refinement;
+            covered_range += refinement;
+
+            let inside = round(position + ray_dir * 0.5);
+            coord = vec2<u32>(clamp(inside, vec2<f32>(0.0), dimensions - 1.0));
+        }
+
+        let index = object_index(coord);
+        if (index != inside_object) {
+            inside_object = index;
+
+            var surface = decode_emission(textureLoad(tex_main, coord, 0).rgb);
+            // Per-object lighting properties of the occluder that got hit
+            let properties = mask2d_properties[index];
+            // Additional emission and opacity written by the mask prepass
+            let emission = textureLoad(tex_emission, coord, 0);
+            surface += properties.emission + emission.rgb;
+            // Light of the previous frame reflected off the surface
+            if (properties.absorption < 1.0) {
+                surface += properties.albedo * (1.0 - properties.absorption)
+                    * (surface_irradiance(position, ray_dir, dimensions)
+                        + direct_irradiance(position - ray_dir * CONSERVATIVE_MARGIN));
+            }
+
+            color += surface * transmittance;
+            // See `Mask2dProperties`
+            let coverage = properties.opacity * emission.a;
+            transmittance *= mix(vec3<f32>(1.0), properties.transmission, coverage);
+            if (all(transmittance < vec3<f32>(MIN_TRANSMITTANCE))) {
+                transmittance = vec3<f32>(0.0);
+                break;
+            }
+        }
+
+        // Cross the occluder a texel at a time
+        let step = conservative_step(position, ray_dir, 0.0);
+        position += ray_dir * step;
+        covered_range += step;
+    }
+
+    let blocked = all(transmittance == vec3<f32>(0.0));
+
+    // Out of steps, the rest of the interval is as foggy as the last step
+    if (!blocked && r == max_raymarch && covered_range < light_hit.dist) {
+        let extinction = exp(-density * (light_hit.dist - covered_range));
+        color += fog.ambient * transmittance * (1.0 - extinction);
+        transmittance *= extinction;
+        // Up to the light ending the interval, if any
+        covered_range = light_hit.dist;
+
+        // A conservative march stopped within a texel of an occluder could be about to hit it
+        if (conservative && dist - CONSERVATIVE_MARGIN < 1.0) {
+            transmittance = vec3<f32>(0.0);
         }
+    }
+
+    // Reached a light through fog and translucent occluders
+    if (!blocked && light_hit.dist < range && covered_range >= light_hit.dist) {
+        color += light_hit.radiance * transmittance;
+        transmittance = vec3<f32>(0.0);
+    }
+
+    // Fog and occluders hide what lies behind them, including the cascades merged in later
+    return RayInterval(color, transmittance);
+}
+
+// Irradiance of the previous frame in front of the surface a ray hit.
+fn surface_irradiance(position: vec2<f32>, ray_dir: vec2<f32>, dimensions: vec2<f32>) -> vec3<f32> {
+    // Probes inside the occluder see no light, back off by a probe spacing
+    let spacing = dimensions / vec2<f32>(textureDimensions(tex_radiance_history));
+    let surface = position - ray_dir * max(spacing.x, spacing.y);
+    let uv = (surface + 0.5) / dimensions;
+
+    return textureSampleLevel(tex_radiance_history, sampler_radiance_history, uv, 0.0).rgb;
+}
+
+// Distance to the closest occluder, bilinearly filtered when enabled.
+fn sample_dist_field(position: vec2<f32>, coord: vec2<u32>, dimensions: vec2<f32>) -> f32 {
+    if (probe.filtered != 0u) {
+        let uv = (position + 0.5) / dimensions;
+        // Texel centers are 0.5 away from the edge of occluder texels
+        return textureSampleLevel(tex_dist_field, sampler_dist_field, uv, 0.0).r - 0.5;
+    }
+
+    return textureLoad(tex_dist_field, coord, 0).r;
+}
+
+// Step that cannot skip over an occluder texel, however thin.
+fn conservative_step(position: vec2<f32>, ray_dir: vec2<f32>, dist: f32) -> f32 {
+    // Free space around the position, wherever it lies within its texel
+    let safe_dist = dist - CONSERVATIVE_MARGIN;
+    if (safe_dist >= 1.0) {
+        return safe_dist;
+    }
+
+    // Close to an occluder, advance to the next texel the ray enters
+    let cell_position = position + 0.5;
+    let boundary = floor(cell_position) + select(vec2<f32>(0.0), vec2<f32>(1.0), ray_dir > vec2<f32>(0.0));
+    let t = select(
+        vec2<f32>(1e9),
+        (boundary - cell_position) / ray_dir,
+        ray_dir != vec2<f32>(0.0)
+    );
+
+    return max(min(t.x, t.y), 0.0) + 1e-3;
+}
+
+// Light emitted by a main pass color, see `EmissionDecoding`.
+fn decode_emission(color: vec3<f32>) -> vec3<f32> {
+    if ((probe.emission_flags & EMISSION_MAIN) == 0u) {
+        return vec3<f32>(0.0);
+    }
+
+    // Colors within the threshold only reflect light
+    let excess = max(abs(color) - probe.emission_threshold, vec3<f32>(0.0));
+    let emission = pow(excess, vec3<f32>(probe.emission_curve)) * probe.emission_scale;
+
+    // Colors below the negative threshold absorb light when enabled
+    if ((probe.emission_flags & EMISSION_NEGATIVE) != 0u) {
+        return sign(color) * emission;
+    }
+
+    return select(vec3<f32>(0.0), emission, color > vec3<f32>(0.0));
+}
+
+// Light of the `DirectLight2d`s in front of a surface, which the radiance history lacks.
+fn direct_irradiance(position: vec2<f32>) -> vec3<f32> {
+    var irradiance = vec3<f32>(0.0);
+
+    for (var l = 0u; l < arrayLength(&lights); l++) {
+        let light = lights[l];
+        if (light.direct != 0u) {
+            irradiance += light2d_direct_irradiance(light, tex_dist_field, position);
+        }
+    }
+
+    return irradiance;
+}
+
+struct LightHit {
+    radiance: vec3<f32>,
+    // Distance along the interval, `range` if no light was entered
+    dist: f32,
+}
+
+fn lights_hit(origin: vec2<f32>, ray_dir: vec2<f32>, start: f32, range: f32) -> LightHit {
+    var light_hit = LightHit(vec3<f32>(0.0), range);
+
+    for (var l = 0u; l < arrayLength(&lights); l++) {
+        let light = lights[l];
+        let t = light2d_intersect(light, origin, ray_dir);
+        if (t < 0.0 || t >= light_hit.dist) {
+            continue;
+        }
+
+        light_hit.dist = t;
+        // Starting inside, the light was entered by the previous interval unless this one
+        // starts at the probe. Direct lights still block rays, but their light is added per
+        // pixel by the apply pass and bounces through `direct_irradiance`.
+        light_hit.radiance = select(
+            vec3<f32>(0.0),
+            light2d_radiance(light, ray_dir, start + t),
+            (t > 0.0 || start == 0.0) && light.direct == 0u
+        );
+    }
+
+    return light_hit;
+}
+
+fn fog_density(position: vec2<f32>) -> f32 {
+    var density = fog.density;
+
+    if (fog.region_density > 0.0) {
+        let uv = (position - fog.region_min) / fog.region_size;
+        if (all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0))) {
+            density += fog.region_density
+                * textureSampleLevel(tex_fog_density, sampler_fog_density, uv, 0.0).r;
+        }
+    }
+
+    return density;
+}
 
-        position += ray_dir * dist;
-        covered_range += dist;
+fn object_index(coord: vec2<u32>) -> u32 {
+    // Dynamic occluders are drawn on top of static ones
+    let index = textureLoad(tex_mask, coord, 0).r;
+    if (index != 0u) {
+        return index;
     }
 
-    return color;
+    return textureLoad(tex_static_mask, coord, 0).r;
 }
 
 fn merge(probe_cell: vec2<u32>, probe_coord: vec2<u32>, ray_index: u32) -> vec4<f32> {
@@ -1,14 +1,222 @@
 #import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+#import bevy_render::maths::{PI, PI_2}
+#import "shaders/light2d.wgsl"::{Light2d, light2d_direct_irradiance};
+#import "shaders/mask2d_types.wgsl"::Mask2dProperties;
+
+const SURFACE_DIRECTIONS: u32 = 8;
+const EPSILON: f32 = 4.88e-04;
+const LUMINANCE: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+// GiUpsampling
+const UPSAMPLING_BICUBIC: u32 = 1;
+const UPSAMPLING_JOINT_BILATERAL: u32 = 2;
+// How quickly probes are rejected as their distance to occluders departs from the pixel's
+const BILATERAL_SHARPNESS: f32 = 4.0;
 
 @group(0) @binding(0) var tex_main: texture_2d<f32>;
 @group(0) @binding(1) var sampler_main: sampler;
 @group(0) @binding(2) var tex_radiance_mipmap: texture_2d<f32>;
 @group(0) @binding(3) var sampler_radiance_mipmap: sampler;
+@group(0) @binding(4) var tex_dist_field: texture_2d<f32>;
+@group(0) @binding(5) var<storage, read> lights: array<Light2d>;
+@group(0) @binding(6) var<uniform> gi: GiSettings;
+@group(0) @binding(7) var tex_mask: texture_2d<u32>;
+@group(0) @binding(8) var tex_static_mask: texture_2d<u32>;
+@group(0) @binding(9) var<storage, read> mask2d_properties: array<Mask2dProperties>;
+@group(0) @binding(10) var tex_emission: texture_2d<f32>;
+@group(0) @binding(11) var tex_radiance_direction: texture_2d<f32>;
+
+struct GiSettings {
+    // Tint multiplied by intensity and exposure
+    tint: vec3<f32>,
+    // Ambient floor multiplied by exposure
+    ambient: vec3<f32>,
+    // How deep into occluders to look for their surface in pixels, 0 to disable
+    surface_depth: f32,
+    // Extra light on the edges of occluders
+    rim_light: f32,
+    // Whether surfaces weight incoming light by the cosine to their normal
+    cosine: u32,
+    // GiUpsampling index
+    upsampling: u32,
+}
 
 @fragment
 fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
     let main = textureSample(tex_main, sampler_main, in.uv);
-    let radiance = textureSample(tex_radiance_mipmap, sampler_radiance_mipmap, in.uv);
+    let radiance = upsample_radiance(in.uv, in.position.xy);
+
+    var lighting = radiance.rgb + direct_lighting(in.position.xy);
+    let coord = vec2<u32>(in.position.xy);
+    if (gi.surface_depth > 0.0 && textureLoad(tex_dist_field, coord, 0).r < EPSILON) {
+        // Probes inside occluders are meaningless, light them from their surface instead
+        let properties = mask2d_properties[object_index(coord)];
+        let emission = textureLoad(tex_emission, coord, 0).rgb + properties.emission;
+        lighting = surface_lighting(in.position.xy) * properties.albedo + emission;
+    }
+
+    lighting = max(lighting * gi.tint, gi.ambient);
+    return main + vec4<f32>(lighting, radiance.a);
+}
+
+// Lighting of a pixel inside an occluder, taken just outside its closest surface.
+fn surface_lighting(pixel: vec2<f32>) -> vec3<f32> {
+    let dimensions = vec2<f32>(textureDimensions(tex_dist_field));
+
+    // Widen rings of directions until one leaves the occluder
+    for (var r = 1.0; r <= gi.surface_depth; r += 1.0) {
+        // Exterior sample furthest from any occluder, its direction crosses the surface nearest
+        var exterior_dist = -1.0;
+        var normal = vec2<f32>(0.0);
+        for (var d = 0u; d < SURFACE_DIRECTIONS; d++) {
+            let angle = f32(d) / f32(SURFACE_DIRECTIONS) * PI_2;
+            let direction = vec2<f32>(cos(angle), sin(angle));
+            let exterior = pixel + direction * r;
+            if (any(exterior >= dimensions) || any(exterior < vec2<f32>(0.0))) {
+                continue;
+            }
+
+            let dist = textureLoad(tex_dist_field, vec2<u32>(exterior), 0).r;
+            if (dist >= EPSILON && dist > exterior_dist) {
+                exterior_dist = dist;
+                normal = direction;
+            }
+        }
+
+        if (exterior_dist >= 0.0) {
+            let exterior = pixel + normal * r;
+            let uv = exterior / dimensions;
+            let lighting = surface_radiance(uv, normal) + direct_lighting(exterior);
+
+            // Fades from the edge towards the inside
+            let rim = gi.rim_light * pow(1.0 - r / (gi.surface_depth + 1.0), 4.0);
+            return lighting * (1.0 + rim);
+        }
+    }
+
+    // Too deep inside to be reached by light
+    return vec3<f32>(0.0);
+}
+
+// GI of a pixel, upsampled from the probe grid with the configured filter.
+fn upsample_radiance(uv: vec2<f32>, pixel: vec2<f32>) -> vec4<f32> {
+    switch gi.upsampling {
+        case UPSAMPLING_BICUBIC: {
+            return sample_bicubic(uv);
+        }
+        case UPSAMPLING_JOINT_BILATERAL: {
+            return sample_joint_bilateral(uv, pixel);
+        }
+        default: {
+            return textureSampleLevel(tex_radiance_mipmap, sampler_radiance_mipmap, uv, 0.0);
+        }
+    }
+}
+
+// Cubic B-spline filter folded into four bilinear taps.
+fn sample_bicubic(uv: vec2<f32>) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(tex_radiance_mipmap));
+    let texel = uv * size - 0.5;
+    let base = floor(texel);
+    let f = texel - base;
+
+    // Weights of the 4 texels around along each axis
+    let w0 = (1.0 - f) * (1.0 - f) * (1.0 - f) / 6.0;
+    let w1 = (4.0 - 6.0 * f * f + 3.0 * f * f * f) / 6.0;
+    let w3 = f * f * f / 6.0;
+    let w2 = 1.0 - w0 - w1 - w3;
+
+    // Each pair of texels is read by one bilinear tap placed between them
+    let s0 = w0 + w1;
+    let s1 = w2 + w3;
+    let uv0 = (base - 0.5 + w1 / s0) / size;
+    let uv1 = (base + 1.5 + w3 / s1) / size;
+
+    return s0.y * (
+        s0.x * textureSampleLevel(tex_radiance_mipmap, sampler_radiance_mipmap, uv0, 0.0)
+        + s1.x * textureSampleLevel(tex_radiance_mipmap, sampler_radiance_mipmap, vec2<f32>(uv1.x, uv0.y), 0.0)
+    ) + s1.y * (
+        s0.x * textureSampleLevel(tex_radiance_mipmap, sampler_radiance_mipmap, vec2<f32>(uv0.x, uv1.y), 0.0)
+        + s1.x * textureSampleLevel(tex_radiance_mipmap, sampler_radiance_mipmap, uv1, 0.0)
+    );
+}
+
+// Bilinear filter guided by the distance field, so that probes on the other side of an occluder
+// or buried inside one do not bleed onto the pixel.
+fn sample_joint_bilateral(uv: vec2<f32>, pixel: vec2<f32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(tex_radiance_mipmap));
+    let dist_size = vec2<f32>(textureDimensions(tex_dist_field));
+    // Pixels between probes
+    let spacing = dist_size / vec2<f32>(size);
+
+    let texel = pixel / spacing - 0.5;
+    let base = vec2<i32>(floor(texel));
+    let f = texel - floor(texel);
+    let dist = textureLoad(tex_dist_field, vec2<u32>(pixel), 0).r;
+
+    var accumulation = vec4<f32>(0.0);
+    var total_weight = 0.0;
+    for (var y = 0; y < 2; y++) {
+        for (var x = 0; x < 2; x++) {
+            let coord = clamp(base + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            let bilinear = select(1.0 - f.x, f.x, x == 1) * select(1.0 - f.y, f.y, y == 1);
+
+            let probe_pixel = min((vec2<f32>(coord) + 0.5) * spacing, dist_size - 1.0);
+            let probe_dist = textureLoad(tex_dist_field, vec2<u32>(probe_pixel), 0).r;
+            var guide = exp(-abs(probe_dist - dist) / spacing.x * BILATERAL_SHARPNESS);
+            if (probe_dist < EPSILON && dist >= EPSILON) {
+                guide = 0.0;
+            }
+
+            let weight = bilinear * guide;
+            accumulation += textureLoad(tex_radiance_mipmap, coord, 0) * weight;
+            total_weight += weight;
+        }
+    }
+
+    // Every probe around got rejected
+    if (total_weight < EPSILON) {
+        return textureSampleLevel(tex_radiance_mipmap, sampler_radiance_mipmap, uv, 0.0);
+    }
+
+    return accumulation / total_weight;
+}
+
+// GI reaching a surface facing `normal`, cosine weighted when enabled.
+fn surface_radiance(uv: vec2<f32>, normal: vec2<f32>) -> vec3<f32> {
+    let radiance = textureSampleLevel(tex_radiance_mipmap, sampler_radiance_mipmap, uv, 0.0).rgb;
+    if (gi.cosine == 0u) {
+        return radiance;
+    }
+
+    // Clamped cosine lobe over the first order harmonic of the luminance,
+    // halved so that a uniform environment is left unchanged
+    let direction = textureSampleLevel(tex_radiance_direction, sampler_radiance_mipmap, uv, 0.0).rg;
+    let luminance = dot(radiance, LUMINANCE);
+    let lobe = 1.0 + PI * 0.25 * dot(direction, normal) / max(luminance, EPSILON);
+    return radiance * max(lobe, 0.0);
+}
+
+fn object_index(coord: vec2<u32>) -> u32 {
+    // Dynamic occluders are drawn on top of static ones
+    let index = textureLoad(tex_mask, coord, 0).r;
+    if (index != 0u) {
+        return index;
+    }
+
+    return textureLoad(tex_static_mask, coord, 0).r;
+}
+
+fn direct_lighting(pixel: vec2<f32>) -> vec3<f32> {
+    var lighting = vec3<f32>(0.0);
+
+    for (var l = 0u; l < arrayLength(&lights); l++) {
+        let light = lights[l];
+        if (light.direct == 0u) {
+            continue;
+        }
+
+        lighting += light2d_direct_irradiance(light, tex_dist_field, pixel);
+    }
 
-    return main + radiance;
+    return lighting;
 }
@@ -1,8 +1,19 @@
-#import "shaders/radiance_probe.wgsl"::Probe;
+#import "shaders/radiance_probe.wgsl"::{Probe, ray_angle, ray_jitter};
+
+const LUMINANCE: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
 
 @group(0) @binding(0) var<uniform> probe: Probe;
 @group(0) @binding(1) var tex_radiance_cascades: texture_2d<f32>;
 @group(0) @binding(2) var tex_radiance_mipmap: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(3) var tex_radiance_direction: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(4) var tex_blue_noise: texture_2d<f32>;
+
+struct ProbeRadiance {
+    // Mean radiance over all directions
+    mean: vec4<f32>,
+    // First order harmonic of the luminance, L(dir) ~= mean + dot(direction, dir)
+    direction: vec2<f32>,
+}
 
 @compute
 @workgroup_size(8, 8, 1)
@@ -16,16 +27,58 @@ fn radiance_cascades_mipmap(
         return;
     }
 
-    let probe_cell = base_coord * probe.width;
-    let ray_count = probe.width * 2;
+    // Jittered probes are blurred with their neighbours to hide the noise
+    let radius = select(0, 1, probe.jitter != 0u);
+
+    var mean = vec4<f32>(0.0);
+    var direction = vec2<f32>(0.0);
+    var total_weight = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let cell = vec2<i32>(base_coord) + vec2<i32>(x, y);
+            if (any(cell < vec2<i32>(0)) || any(cell >= vec2<i32>(dimensions))) {
+                continue;
+            }
+
+            // Tent filter
+            let weight = f32((2 - abs(x)) * (2 - abs(y)));
+            let radiance = probe_radiance(vec2<u32>(cell));
+            mean += radiance.mean * weight;
+            direction += radiance.direction * weight;
+            total_weight += weight;
+        }
+    }
+
+    textureStore(tex_radiance_mipmap, base_coord, mean / total_weight);
+    if (probe.cosine != 0u) {
+        textureStore(
+            tex_radiance_direction,
+            base_coord,
+            vec4<f32>(direction / total_weight, 0.0, 0.0)
+        );
+    }
+}
+
+// Integrates the directions of a cascade 0 probe, weighted on the CPU, see `direction_weight`
+// and `harmonic_weight` in radiance_cascades.rs.
+fn probe_radiance(probe_cell: vec2<u32>) -> ProbeRadiance {
+    let probe_coord = probe_cell * probe.width;
+    // Same ray layout and jitter as the cascades
+    let jitter = ray_jitter(probe, tex_blue_noise, probe_cell).x;
 
-    var accumulation = vec4<f32>(0.0);
+    var mean = vec4<f32>(0.0);
+    var direction = vec2<f32>(0.0);
     for (var y: u32 = 0; y < probe.width; y++) {
         for (var x: u32 = 0; x < probe.width; x++) {
-            accumulation += textureLoad(tex_radiance_cascades, probe_cell + vec2<u32>(x, y), 0);
+            let radiance = textureLoad(tex_radiance_cascades, probe_coord + vec2<u32>(x, y), 0);
+            mean += radiance;
+
+            if (probe.cosine != 0u) {
+                let angle = ray_angle(probe, x + y * probe.width, jitter);
+                direction += vec2<f32>(cos(angle), sin(angle)) * dot(radiance.rgb, LUMINANCE);
+            }
         }
     }
-    accumulation /= f32(ray_count);
 
-    textureStore(tex_radiance_mipmap, base_coord, accumulation);
+    return ProbeRadiance(mean * probe.direction_weight, direction * probe.harmonic_weight);
 }
@@ -1,5 +1,48 @@
+#import bevy_render::maths::PI_2
+
+const GOLDEN_RATIO: f32 = 0.618034;
+
 struct Probe {
     width: u32,
     start: f32,
     range: f32,
+    emission_threshold: f32,
+    emission_scale: f32,
+    emission_curve: f32,
+    emission_flags: u32,
+    conservative: u32,
+    filtered: u32,
+    jitter: u32,
+    jitter_seed: u32,
+    frame_index: u32,
+    cosine: u32,
+    direction_weight: f32,
+    harmonic_weight: f32,
+}
+
+const EMISSION_MAIN: u32 = 1u;
+const EMISSION_NEGATIVE: u32 = 2u;
+
+// Angle of a ray of the probe, `jitter` in ray spacings.
+fn ray_angle(probe: Probe, ray_index: u32, jitter: f32) -> f32 {
+    return (f32(ray_index) + 0.5 + jitter) / f32(probe.width * probe.width) * PI_2;
+}
+
+// Per probe offsets in [-0.5, 0.5) of the ray angles, in ray spacings, and of the interval start.
+fn ray_jitter(probe: Probe, tex_blue_noise: texture_2d<f32>, probe_cell: vec2<u32>) -> vec2<f32> {
+    if (probe.jitter == 0u) {
+        return vec2<f32>(0.0);
+    }
+
+    // The seed picks the tile offset, each cascade reads another part of the tile
+    let size = textureDimensions(tex_blue_noise);
+    let offset = vec2<u32>(
+        probe.jitter_seed * 7919u + probe.width * 31u,
+        probe.jitter_seed * 104729u + probe.width * 17u
+    );
+    let noise = textureLoad(tex_blue_noise, (probe_cell + offset) % size, 0).rg;
+
+    // Golden ratio steps cycle each texel through all values while keeping every frame blue
+    let frame = f32(probe.frame_index % 4096u);
+    return fract(noise + frame * GOLDEN_RATIO) - 0.5;
 }
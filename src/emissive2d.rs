@@ -0,0 +1,165 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{AsBindGroup, AsBindGroupShaderType, ShaderRef, ShaderType},
+        texture::GpuImage,
+    },
+    sprite::{Material2d, Material2dPlugin},
+};
+
+use crate::{
+    light_animation2d::Keyframes2d,
+    mask2d::{Mask2dMaterial, Mask2dMaterialPlugin},
+};
+
+/// Renders [`EmissiveMaterial2d`] meshes into the main pass and their emission into the mask
+/// prepass, where the cascades pick up its spatially varying colors.
+pub struct EmissiveMaterial2dPlugin;
+
+impl Plugin for EmissiveMaterial2dPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<EmissiveMaterial2d>::default())
+            .add_plugins(Mask2dMaterialPlugin::<EmissiveMaterial2d>::default());
+    }
+}
+
+/// A [`ColorMaterial`]-like material whose emission is textured and animated,
+/// e.g. stained-glass windows, flickering neon signs or projector patterns.
+///
+/// The emission only reaches the screen through the cascades, attach [`Mask2d`][crate::mask2d::Mask2d]
+/// for it to light the scene.
+#[derive(Asset, AsBindGroup, TypePath, Debug, Clone)]
+#[uniform(0, EmissiveMaterial2dUniform)]
+pub struct EmissiveMaterial2d {
+    /// Color of the surface in the main pass.
+    pub color: Color,
+    /// Texture of the surface in the main pass, its alpha is also used by
+    /// [`Mask2dAlphaCutoff`][crate::mask2d::Mask2dAlphaCutoff].
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Option<Handle<Image>>,
+    /// Color of the emitted light.
+    pub emission: Color,
+    /// Multiplier of the emission color.
+    pub emission_intensity: f32,
+    /// Pattern multiplied with the emission color.
+    #[texture(3)]
+    #[sampler(4)]
+    pub emission_texture: Option<Handle<Image>>,
+    /// Scrolling of the emission texture in uv units per second, the sampler should repeat.
+    pub emission_scroll: Vec2,
+    /// Multiplier of the emission over time, sampled on the GPU from up to
+    /// [`EmissionCurve2d::MAX_KEYS`] keys.
+    pub emission_curve: EmissionCurve2d,
+}
+
+impl Default for EmissiveMaterial2d {
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            texture: None,
+            emission: Color::WHITE,
+            emission_intensity: 1.0,
+            emission_texture: None,
+            emission_scroll: Vec2::ZERO,
+            emission_curve: EmissionCurve2d::default(),
+        }
+    }
+}
+
+impl Material2d for EmissiveMaterial2d {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/emissive_material2d.wgsl".into()
+    }
+}
+
+impl Mask2dMaterial for EmissiveMaterial2d {
+    fn mask_fragment_shader() -> ShaderRef {
+        "shaders/emissive_material2d_mask.wgsl".into()
+    }
+
+    fn mask_texture_alpha() -> bool {
+        true
+    }
+}
+
+/// Looping [`Keyframes2d`] of an emission multiplier, sampled on the GPU by the same rules as
+/// [`LightAnimation2d::curve`][crate::light_animation2d::LightAnimation2d::curve]. An empty curve
+/// is a constant multiplier of 1.
+#[derive(Default, Debug, Clone)]
+pub struct EmissionCurve2d(Keyframes2d<f32>);
+
+impl EmissionCurve2d {
+    /// Keys uploaded to the GPU, the ones with the latest times beyond this count are ignored.
+    pub const MAX_KEYS: usize = 8;
+
+    /// Curve from `(time in seconds, multiplier)` keys in any order, keeping the first
+    /// [`Self::MAX_KEYS`] by time.
+    pub fn new(keys: impl IntoIterator<Item = (f32, f32)>) -> Self {
+        let mut keyframes = Keyframes2d::new(keys);
+        if keyframes.keys().len() > Self::MAX_KEYS {
+            warn!(
+                "EmissionCurve2d supports up to {} keys, the rest are ignored.",
+                Self::MAX_KEYS
+            );
+            keyframes.truncate(Self::MAX_KEYS);
+        }
+
+        Self(keyframes)
+    }
+
+    pub fn keys(&self) -> &[(f32, f32)] {
+        self.0.keys()
+    }
+
+    /// Multiplier at `time` seconds, as sampled by the material's shaders.
+    pub fn sample(&self, time: f32) -> f32 {
+        self.0.sample_or(time, 1.0)
+    }
+}
+
+const EMISSIVE_MATERIAL_TEXTURE_BIT: u32 = 1 << 0;
+const EMISSIVE_MATERIAL_EMISSION_TEXTURE_BIT: u32 = 1 << 1;
+
+/// The GPU representation of the uniform data of an [`EmissiveMaterial2d`].
+#[derive(ShaderType, Clone, Default)]
+pub struct EmissiveMaterial2dUniform {
+    pub color: Vec4,
+    /// Emission color multiplied by intensity.
+    pub emission: Vec3,
+    pub flags: u32,
+    pub emission_scroll: Vec2,
+    pub curve_len: u32,
+    /// Keys of the emission curve as `(time, multiplier, _, _)`.
+    pub curve: [Vec4; EmissionCurve2d::MAX_KEYS],
+}
+
+impl AsBindGroupShaderType<EmissiveMaterial2dUniform> for EmissiveMaterial2d {
+    fn as_bind_group_shader_type(
+        &self,
+        _images: &RenderAssets<GpuImage>,
+    ) -> EmissiveMaterial2dUniform {
+        let mut flags = 0;
+        if self.texture.is_some() {
+            flags |= EMISSIVE_MATERIAL_TEXTURE_BIT;
+        }
+        if self.emission_texture.is_some() {
+            flags |= EMISSIVE_MATERIAL_EMISSION_TEXTURE_BIT;
+        }
+
+        let mut curve = [Vec4::ZERO; EmissionCurve2d::MAX_KEYS];
+        for (key, &(time, multiplier)) in curve.iter_mut().zip(self.emission_curve.keys()) {
+            *key = Vec4::new(time, multiplier, 0.0, 0.0);
+        }
+
+        EmissiveMaterial2dUniform {
+            color: self.color.to_linear().to_vec4(),
+            emission: self.emission.to_linear().to_vec3() * self.emission_intensity,
+            flags,
+            emission_scroll: self.emission_scroll,
+            curve_len: self.emission_curve.keys().len() as u32,
+            curve,
+        }
+    }
+}
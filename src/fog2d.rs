@@ -0,0 +1,135 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::{ShaderType, TextureView, UniformBuffer},
+        renderer::{RenderDevice, RenderQueue},
+        texture::{FallbackImage, GpuImage},
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::light2d::CascadePixelSpace;
+use crate::radiance_cascades::RadianceCascadesConfig;
+
+/// Extracts the [`Fog2d`] of radiance cascades cameras.
+pub struct Fog2dPlugin;
+
+impl Plugin for Fog2dPlugin {
+    fn build(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .add_systems(ExtractSchedule, extract_fog2d)
+            .add_systems(Render, prepare_fog2d.in_set(RenderSet::PrepareResources));
+    }
+}
+
+/// Attach to a camera alongside [`RadianceCascadesConfig`] to fill the space between
+/// occluders with fog that absorbs light along rays and scatters ambient light into them.
+///
+/// Lights marked with [`DirectLight2d`][crate::light2d::DirectLight2d] are not affected.
+#[derive(Component, Clone, Default)]
+pub struct Fog2d {
+    /// Extinction coefficient per world unit travelled, everywhere. Light crossing a distance `d`
+    /// keeps `exp(-density * d)` of itself, so values above 1 are valid.
+    pub density: f32,
+    /// Light scattered into rays by the fog, reached once a ray is fully obscured.
+    pub ambient: Color,
+    /// Density added inside [`region`](Self::region), multiplied by the red channel of
+    /// [`density_texture`](Self::density_texture) if any.
+    pub region_density: f32,
+    /// World space area covered by [`region_density`](Self::region_density).
+    pub region: Rect,
+    /// Density pattern stretched over [`region`](Self::region), e.g. dust clouds.
+    pub density_texture: Option<Handle<Image>>,
+}
+
+/// Fog in the pixel space of a view.
+#[derive(ShaderType, Default, Debug, Clone, Copy)]
+pub struct GpuFog2d {
+    pub ambient: Vec3,
+    /// Density per pixel.
+    pub density: f32,
+    pub region_min: Vec2,
+    pub region_size: Vec2,
+    /// Region density per pixel.
+    pub region_density: f32,
+}
+
+#[derive(Component)]
+pub struct ExtractedFog2d {
+    fog: GpuFog2d,
+    density_texture: Option<AssetId<Image>>,
+}
+
+/// Fog uniform and density texture of a view, zero density if the camera has no [`Fog2d`].
+#[derive(Component)]
+pub struct Fog2dBuffer {
+    pub uniform: UniformBuffer<GpuFog2d>,
+    pub density_texture: TextureView,
+}
+
+type ExtractedFogCameraQuery = (
+    Entity,
+    &'static Camera,
+    &'static GlobalTransform,
+    &'static Fog2d,
+);
+
+fn extract_fog2d(
+    mut commands: Commands,
+    q_cameras: Extract<Query<ExtractedFogCameraQuery, With<RadianceCascadesConfig>>>,
+) {
+    for (entity, camera, camera_transform, fog) in q_cameras.iter() {
+        let (true, Some(viewport)) = (camera.is_active, camera.physical_viewport_rect()) else {
+            continue;
+        };
+
+        let pixel_space = CascadePixelSpace::new(camera, camera_transform, viewport);
+        let to_pixel = |world: Vec2| pixel_space.to_pixel(world.extend(0.0));
+        let pixels_per_unit = pixel_space.pixels_per_unit();
+        let (region_a, region_b) = (to_pixel(fog.region.min), to_pixel(fog.region.max));
+
+        commands.get_or_spawn(entity).insert(ExtractedFog2d {
+            fog: GpuFog2d {
+                ambient: fog.ambient.to_linear().to_vec3(),
+                density: fog.density / pixels_per_unit,
+                region_min: region_a.min(region_b),
+                region_size: (region_a - region_b).abs(),
+                region_density: fog.region_density / pixels_per_unit,
+            },
+            density_texture: fog.density_texture.as_ref().map(Handle::id),
+        });
+    }
+}
+
+fn prepare_fog2d(
+    mut commands: Commands,
+    q_views: Query<(Entity, Option<&ExtractedFog2d>), With<RadianceCascadesConfig>>,
+    images: Res<RenderAssets<GpuImage>>,
+    fallback_image: Res<FallbackImage>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    for (entity, fog) in q_views.iter() {
+        let mut uniform = UniformBuffer::from(fog.map(|fog| fog.fog).unwrap_or_default());
+        uniform.set_label(Some("fog2d_uniform"));
+        uniform.write_buffer(&render_device, &render_queue);
+
+        // The white fallback leaves the region density as is
+        let density_texture = fog
+            .and_then(|fog| fog.density_texture)
+            .and_then(|id| images.get(id))
+            .unwrap_or(&fallback_image.d2)
+            .texture_view
+            .clone();
+
+        commands.entity(entity).insert(Fog2dBuffer {
+            uniform,
+            density_texture,
+        });
+    }
+}
@@ -1,6 +1,9 @@
 use bevy::{
     core_pipeline::core_2d::graph::Core2d,
-    ecs::query::QueryItem,
+    ecs::{
+        entity::{EntityHashMap, EntityHashSet},
+        query::QueryItem,
+    },
     prelude::*,
     render::{
         extract_component::{ExtractComponent, ExtractComponentPlugin},
@@ -10,20 +13,29 @@ use bevy::{
         render_resource::{
             binding_types::{texture_2d, texture_storage_2d, uniform_buffer},
             BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries,
-            CachedComputePipelineId, ComputePassDescriptor, ComputePipelineDescriptor,
-            DynamicUniformBuffer, PipelineCache, ShaderStages, StorageTextureAccess,
-            TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+            CachedComputePipelineId, ComputePassDescriptor, ComputePipeline,
+            ComputePipelineDescriptor, DynamicUniformBuffer, PipelineCache, ShaderStages,
+            StorageTextureAccess, TextureDescriptor, TextureDimension, TextureFormat,
+            TextureSampleType, TextureUsages, TextureViewDescriptor, UniformBuffer,
         },
         renderer::{RenderContext, RenderDevice, RenderQueue},
         texture::{CachedTexture, TextureCache},
-        view::ViewTarget,
+        view::{ExtractedView, ViewTarget},
         Render, RenderApp, RenderSet,
     },
 };
 
-use crate::math_util::{batch_count, fast_log2_ceil};
+use crate::{
+    mask2d::StaticMask2dDirty,
+    math_util::{batch_count, fast_log2_ceil},
+};
 
 const MAX_ITER: usize = 16;
+/// Distance the seeds of newly exposed static occluders are flooded into the view when it scrolls.
+const STATIC_SCROLL_MARGIN: u32 = 32;
+/// Distance the view may scroll before the static cache is flooded again from scratch, which
+/// catches up on occluders farther than [`STATIC_SCROLL_MARGIN`] from the exposed strips.
+const STATIC_SCROLL_REFLOOD: u32 = 256;
 
 pub struct JfaPrepassPlugin;
 
@@ -36,12 +48,17 @@ impl Plugin for JfaPrepassPlugin {
         };
 
         render_app
+            .init_resource::<JfaStaticCaches>()
             .add_render_graph_node::<ViewNodeRunner<JfaPrepassNode>>(Core2d, JfaPrepassLabel)
             .add_render_graph_edges(Core2d, (crate::mask2d::Mask2dPrepassLabel, JfaPrepassLabel))
             .add_systems(
                 Render,
                 (
-                    prepare_jfa_textures.in_set(RenderSet::PrepareResources),
+                    (
+                        prepare_jfa_textures,
+                        prepare_jfa_static_caches.after(crate::mask2d::prepare_mask2d_texture),
+                    )
+                        .in_set(RenderSet::PrepareResources),
                     prepare_jfa_bind_groups.in_set(RenderSet::PrepareBindGroups),
                 ),
             );
@@ -69,68 +86,76 @@ pub struct JfaPrepassNode;
 impl ViewNode for JfaPrepassNode {
     type ViewQuery = (
         &'static ViewTarget,
+        &'static JfaPrepassTextures,
         &'static JfaPrepassBindGroups,
         &'static JfaPrepassIterCount,
     );
 
     fn run(
         &self,
-        _graph: &mut RenderGraphContext,
+        graph: &mut RenderGraphContext,
         render_context: &mut RenderContext,
-        (view, bind_groups, iter_count): QueryItem<Self::ViewQuery>,
+        (view, textures, bind_groups, iter_count): QueryItem<Self::ViewQuery>,
         world: &World,
     ) -> Result<(), NodeRunError> {
         let pipeline = world.resource::<JfaPrepassPipeline>();
         let pipeline_cache = world.resource::<PipelineCache>();
+        let static_caches = world.resource::<JfaStaticCaches>();
 
         // Get the pipeline from the cache
-        let (Some(jfa_mask_pipeline), Some(jfa_pipeline)) = (
+        let (Some(jfa_mask_pipeline), Some(jfa_static_shift_pipeline), Some(jfa_pipeline)) = (
             pipeline_cache.get_compute_pipeline(pipeline.jfa_mask_pipeline),
+            pipeline_cache.get_compute_pipeline(pipeline.jfa_static_shift_pipeline),
             pipeline_cache.get_compute_pipeline(pipeline.jfa_pipeline),
         ) else {
             return Ok(());
         };
 
+        let Some(static_cache) = static_caches.0.get(&graph.view_entity()) else {
+            return Ok(());
+        };
+
         render_context
             .command_encoder()
             .push_debug_group("jfa_pass_group");
 
-        let size = view.main_texture().size();
+        let mut size = view.main_texture().size();
+        size.depth_or_array_layers = 1;
         let workgroup_size =
             batch_count(UVec3::new(size.width, size.height, 1), UVec3::new(8, 8, 1));
 
-        {
-            // Jfa mask
-            let mut jfa_mask_compute_pass =
+        let seed = |render_context: &mut RenderContext,
+                    seed_pipeline: &ComputePipeline,
+                    seed_bind_group: &BindGroup,
+                    label: &'static str| {
+            let mut jfa_seed_compute_pass =
                 render_context
                     .command_encoder()
                     .begin_compute_pass(&ComputePassDescriptor {
-                        label: Some("jfa_mask_pass"),
+                        label: Some(label),
                         timestamp_writes: None,
                     });
 
-            jfa_mask_compute_pass.set_pipeline(jfa_mask_pipeline);
-            jfa_mask_compute_pass.set_bind_group(0, &bind_groups.jfa_mask_bind_group, &[]);
-            jfa_mask_compute_pass.dispatch_workgroups(
+            jfa_seed_compute_pass.set_pipeline(seed_pipeline);
+            jfa_seed_compute_pass.set_bind_group(0, seed_bind_group, &[]);
+            jfa_seed_compute_pass.dispatch_workgroups(
                 workgroup_size.x,
                 workgroup_size.y,
                 workgroup_size.z,
             );
-        }
+        };
 
-        {
-            //Jfa
+        let flood = |render_context: &mut RenderContext, iter_count: usize, label: &'static str| {
             let mut jfa_compute_pass =
                 render_context
                     .command_encoder()
                     .begin_compute_pass(&ComputePassDescriptor {
-                        label: Some("jfa_pass"),
+                        label: Some(label),
                         timestamp_writes: None,
                     });
 
             jfa_compute_pass.set_pipeline(jfa_pipeline);
 
-            let iter_count = iter_count.0;
             for i in 0..iter_count {
                 let offset_index = iter_count - i - 1;
 
@@ -152,8 +177,50 @@ impl ViewNode for JfaPrepassNode {
                     workgroup_size.z,
                 );
             }
+        };
+
+        match static_cache.update {
+            JfaStaticCacheUpdate::None => {}
+            JfaStaticCacheUpdate::Scroll {
+                iter_count: scroll_iter_count,
+            } => {
+                // Move the cached seeds along with the view and flood the exposed strips
+                seed(
+                    render_context,
+                    jfa_static_shift_pipeline,
+                    &bind_groups.jfa_static_shift_bind_group,
+                    "jfa_static_shift_pass",
+                );
+                flood(render_context, scroll_iter_count, "jfa_static_pass");
+            }
+            JfaStaticCacheUpdate::Full => {
+                // Flood static occluders and keep the result around for the following frames
+                seed(
+                    render_context,
+                    jfa_mask_pipeline,
+                    &bind_groups.jfa_static_mask_bind_group,
+                    "jfa_static_mask_pass",
+                );
+                flood(render_context, iter_count.0, "jfa_static_pass");
+            }
+        }
+
+        if static_cache.update != JfaStaticCacheUpdate::None {
+            render_context.command_encoder().copy_texture_to_texture(
+                textures.main_texture().texture.as_image_copy(),
+                static_cache.texture.texture.as_image_copy(),
+                size,
+            );
         }
 
+        seed(
+            render_context,
+            jfa_mask_pipeline,
+            &bind_groups.jfa_mask_bind_group,
+            "jfa_mask_pass",
+        );
+        flood(render_context, iter_count.0, "jfa_pass");
+
         render_context.command_encoder().pop_debug_group();
 
         Ok(())
@@ -163,8 +230,10 @@ impl ViewNode for JfaPrepassNode {
 #[derive(Resource)]
 struct JfaPrepassPipeline {
     jfa_mask_bind_group_layout: BindGroupLayout,
+    jfa_static_shift_bind_group_layout: BindGroupLayout,
     jfa_bind_group_layout: BindGroupLayout,
     jfa_mask_pipeline: CachedComputePipelineId,
+    jfa_static_shift_pipeline: CachedComputePipelineId,
     jfa_pipeline: CachedComputePipelineId,
     jfa_step_size_buffers: DynamicUniformBuffer<i32>,
     jfa_step_size_buffer_offsets: Vec<u32>,
@@ -178,6 +247,7 @@ impl FromWorld for JfaPrepassPipeline {
 
         // Shader
         let jfa_mask_shader = world.load_asset("shaders/jfa_mask.wgsl");
+        let jfa_static_shift_shader = world.load_asset("shaders/jfa_static_shift.wgsl");
         let jfa_shader = world.load_asset("shaders/jfa.wgsl");
 
         // Buffer
@@ -209,6 +279,26 @@ impl FromWorld for JfaPrepassPipeline {
             ),
         );
 
+        let jfa_static_shift_bind_group_layout = render_device.create_bind_group_layout(
+            "jfa_static_shift_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    // Scroll offset
+                    uniform_buffer::<IVec2>(false),
+                    // Static mask texture
+                    texture_2d(TextureSampleType::Uint),
+                    // Static jfa cache texture
+                    texture_2d(TextureSampleType::Sint),
+                    // Jfa texture
+                    texture_storage_2d(
+                        JfaPrepassTextures::JFA_FORMAT,
+                        StorageTextureAccess::WriteOnly,
+                    ),
+                ),
+            ),
+        );
+
         let jfa_bind_group_layout = render_device.create_bind_group_layout(
             "jfa_bind_group_layout",
             &BindGroupLayoutEntries::sequential(
@@ -216,7 +306,7 @@ impl FromWorld for JfaPrepassPipeline {
                 (
                     uniform_buffer::<u32>(true),
                     // Jfa texture source
-                    texture_2d(TextureSampleType::Uint),
+                    texture_2d(TextureSampleType::Sint),
                     // Jfa texture destination
                     texture_storage_2d(
                         JfaPrepassTextures::JFA_FORMAT,
@@ -236,6 +326,16 @@ impl FromWorld for JfaPrepassPipeline {
             push_constant_ranges: vec![],
         });
 
+        let jfa_static_shift_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("jfa_static_shift_pipeline".into()),
+                layout: vec![jfa_static_shift_bind_group_layout.clone()],
+                shader: jfa_static_shift_shader,
+                shader_defs: vec![],
+                entry_point: "jfa_static_shift".into(),
+                push_constant_ranges: vec![],
+            });
+
         let jfa_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
             label: Some("jfa_pipeline".into()),
             layout: vec![jfa_bind_group_layout.clone()],
@@ -247,8 +347,10 @@ impl FromWorld for JfaPrepassPipeline {
 
         Self {
             jfa_mask_bind_group_layout,
+            jfa_static_shift_bind_group_layout,
             jfa_bind_group_layout,
             jfa_mask_pipeline,
+            jfa_static_shift_pipeline,
             jfa_pipeline,
             jfa_step_size_buffers,
             jfa_step_size_buffer_offsets,
@@ -267,7 +369,8 @@ pub struct JfaPrepassTextures {
 }
 
 impl JfaPrepassTextures {
-    const JFA_FORMAT: TextureFormat = TextureFormat::Rg16Uint;
+    /// Signed, so that static seeds scrolled out of view can be kept.
+    const JFA_FORMAT: TextureFormat = TextureFormat::Rg16Sint;
 
     /// Access the [`CachedTexture`] that is last written to
     /// based on the [flip][JfaPrepassTextures::flip] boolean.
@@ -279,9 +382,98 @@ impl JfaPrepassTextures {
     }
 }
 
+/// Per view cache of the jump flood of [`StaticMask2d`][crate::mask2d::StaticMask2d] occluders.
+#[derive(Resource, Default)]
+pub struct JfaStaticCaches(EntityHashMap<JfaStaticCache>);
+
+impl JfaStaticCaches {
+    /// Access the cached static jump flood texture of a view.
+    pub fn get(&self, view_entity: &Entity) -> Option<&CachedTexture> {
+        self.0.get(view_entity).map(|cache| &cache.texture)
+    }
+}
+
+pub struct JfaStaticCache {
+    texture: CachedTexture,
+    /// View projection the cached seeds line up with.
+    clip_from_world: Mat4,
+    /// Pixels scrolled along each axis since the last full flood.
+    scrolled: UVec2,
+    /// Pixel offset the cached seeds are shifted by when scrolling.
+    offset_buffer: UniformBuffer<IVec2>,
+    /// How the cache is brought up to date this frame.
+    update: JfaStaticCacheUpdate,
+    /// Whether the pipelines were ready when the last update was decided. Until then the node
+    /// skips the flood, so the cache is flooded fully once they are.
+    ready: bool,
+}
+
+impl JfaStaticCache {
+    /// Decide how to bring the cache up to date with the view's new projection.
+    fn next_update(
+        &mut self,
+        clip_from_world: Mat4,
+        is_orthographic: bool,
+        static_mask_dirty: bool,
+        iter_count: usize,
+    ) -> JfaStaticCacheUpdate {
+        let size = self.texture.texture.size();
+        // Clip space to pixels, y points down in pixels
+        let clip_to_pixel = Vec2::new(size.width as f32, -(size.height as f32)) * 0.5;
+
+        // Zooming and rotating move every pixel differently, scrolling moves them all alike
+        let scroll_only = is_orthographic
+            && clip_from_world.x_axis == self.clip_from_world.x_axis
+            && clip_from_world.y_axis == self.clip_from_world.y_axis
+            && clip_from_world.z_axis == self.clip_from_world.z_axis;
+        let offset = ((clip_from_world.w_axis.xy() - self.clip_from_world.w_axis.xy())
+            * clip_to_pixel)
+            .round()
+            .as_ivec2();
+        let scrolled = self.scrolled + offset.abs().as_uvec2();
+
+        if static_mask_dirty || !scroll_only || scrolled.max_element() > STATIC_SCROLL_REFLOOD {
+            self.clip_from_world = clip_from_world;
+            self.scrolled = UVec2::ZERO;
+            return JfaStaticCacheUpdate::Full;
+        }
+
+        if offset == IVec2::ZERO {
+            return JfaStaticCacheUpdate::None;
+        }
+
+        // Only whole pixels are shifted, the remainder is caught up on in later frames
+        self.clip_from_world.w_axis += (offset.as_vec2() / clip_to_pixel).extend(0.0).extend(0.0);
+        self.scrolled = scrolled;
+        self.offset_buffer.set(offset);
+
+        // Enough steps to carry the seeds across the exposed strips and the margin
+        let reach = offset.abs().max_element() as u32 + STATIC_SCROLL_MARGIN;
+        let mut scroll_iter_count = usize::min(fast_log2_ceil(reach) as usize, iter_count);
+        // Same parity as the full flood, so that the result ends up in the main texture
+        scroll_iter_count += (iter_count - scroll_iter_count) % 2;
+
+        JfaStaticCacheUpdate::Scroll {
+            iter_count: scroll_iter_count,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum JfaStaticCacheUpdate {
+    /// The cache still lines up with the view.
+    None,
+    /// The view scrolled by whole pixels, shift the cached seeds and flood the exposed strips.
+    Scroll { iter_count: usize },
+    /// Flood all static occluders again.
+    Full,
+}
+
 #[derive(Component)]
 pub struct JfaPrepassBindGroups {
     jfa_mask_bind_group: BindGroup,
+    jfa_static_mask_bind_group: BindGroup,
+    jfa_static_shift_bind_group: BindGroup,
     jfa_01_bind_group: BindGroup,
     jfa_10_bind_group: BindGroup,
 }
@@ -303,7 +495,9 @@ fn prepare_jfa_textures(
             sample_count: 1,
             dimension: TextureDimension::D2,
             format: JfaPrepassTextures::JFA_FORMAT,
-            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+            usage: TextureUsages::STORAGE_BINDING
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
             view_formats: &[],
         };
 
@@ -326,6 +520,110 @@ fn prepare_jfa_textures(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
+fn prepare_jfa_static_caches(
+    q_views: Query<
+        (
+            Entity,
+            &ViewTarget,
+            &ExtractedView,
+            Option<&crate::mask2d::Mask2dPrepassTexture>,
+        ),
+        With<JfaPrepass>,
+    >,
+    static_mask_dirty: Res<StaticMask2dDirty>,
+    mut static_caches: ResMut<JfaStaticCaches>,
+    pipeline: Res<JfaPrepassPipeline>,
+    pipeline_cache: Res<PipelineCache>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut live_entities: Local<EntityHashSet>,
+) {
+    live_entities.clear();
+
+    // Same pipelines the node needs to run at all
+    let ready = [
+        pipeline.jfa_mask_pipeline,
+        pipeline.jfa_static_shift_pipeline,
+        pipeline.jfa_pipeline,
+    ]
+    .into_iter()
+    .all(|id| pipeline_cache.get_compute_pipeline(id).is_some());
+
+    for (entity, view_target, view, mask_texture) in q_views.iter() {
+        // An unresolved static mask is empty, keep flooding it fully until it is not
+        let ready = ready && mask_texture.is_none_or(|texture| texture.is_resolved());
+
+        let mut size = view_target.main_texture().size();
+        size.depth_or_array_layers = 1;
+
+        let clip_from_world = view.clip_from_world.unwrap_or_else(|| {
+            view.clip_from_view * view.world_from_view.compute_matrix().inverse()
+        });
+
+        live_entities.insert(entity);
+
+        // Reuse the cache as long as the view has the same size
+        if let Some(cache) = static_caches.0.get_mut(&entity) {
+            if cache.texture.texture.size() == size {
+                let iter_count = usize::min(
+                    fast_log2_ceil(u32::max(size.width, size.height)) as usize,
+                    MAX_ITER,
+                );
+                cache.update = cache.next_update(
+                    clip_from_world,
+                    view.clip_from_view.w_axis.w == 1.0,
+                    static_mask_dirty.0 || !cache.ready,
+                    iter_count,
+                );
+                cache.ready = ready;
+                if let JfaStaticCacheUpdate::Scroll { .. } = cache.update {
+                    cache
+                        .offset_buffer
+                        .write_buffer(&render_device, &render_queue);
+                }
+                continue;
+            }
+        }
+
+        let texture = render_device.create_texture(&TextureDescriptor {
+            label: Some("jfa_static_cache_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: JfaPrepassTextures::JFA_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let default_view = texture.create_view(&TextureViewDescriptor::default());
+
+        let mut offset_buffer = UniformBuffer::default();
+        offset_buffer.set_label(Some("jfa_static_offset_buffer"));
+        offset_buffer.write_buffer(&render_device, &render_queue);
+
+        static_caches.0.insert(
+            entity,
+            JfaStaticCache {
+                texture: CachedTexture {
+                    texture,
+                    default_view,
+                },
+                clip_from_world,
+                scrolled: UVec2::ZERO,
+                offset_buffer,
+                update: JfaStaticCacheUpdate::Full,
+                ready,
+            },
+        );
+    }
+
+    // Clear out all dead views.
+    static_caches
+        .0
+        .retain(|view_entity, _| live_entities.contains(view_entity));
+}
+
 fn prepare_jfa_bind_groups(
     mut commands: Commands,
     q_views: Query<(
@@ -333,10 +631,18 @@ fn prepare_jfa_bind_groups(
         &crate::mask2d::Mask2dPrepassTexture,
         &JfaPrepassTextures,
     )>,
+    static_caches: Res<JfaStaticCaches>,
     render_device: Res<RenderDevice>,
     pipeline: Res<JfaPrepassPipeline>,
 ) {
     for (entity, mask_texture, jfa_textures) in q_views.iter() {
+        let Some(static_cache) = static_caches.0.get(&entity) else {
+            continue;
+        };
+        let Some(offset_binding) = static_cache.offset_buffer.binding() else {
+            continue;
+        };
+
         let jfa_mask_bind_group = render_device.create_bind_group(
             "jfa_mask_bind_group",
             &pipeline.jfa_mask_bind_group_layout,
@@ -346,6 +652,26 @@ fn prepare_jfa_bind_groups(
             )),
         );
 
+        let jfa_static_mask_bind_group = render_device.create_bind_group(
+            "jfa_static_mask_bind_group",
+            &pipeline.jfa_mask_bind_group_layout,
+            &BindGroupEntries::sequential((
+                &mask_texture.get_static().default_view,
+                &jfa_textures.jfa_texture0.default_view,
+            )),
+        );
+
+        let jfa_static_shift_bind_group = render_device.create_bind_group(
+            "jfa_static_shift_bind_group",
+            &pipeline.jfa_static_shift_bind_group_layout,
+            &BindGroupEntries::sequential((
+                offset_binding,
+                &mask_texture.get_static().default_view,
+                &static_cache.texture.default_view,
+                &jfa_textures.jfa_texture0.default_view,
+            )),
+        );
+
         let jfa_01_bind_group = render_device.create_bind_group(
             "jfa_01_bind_group",
             &pipeline.jfa_bind_group_layout,
@@ -368,6 +694,8 @@ fn prepare_jfa_bind_groups(
 
         commands.entity(entity).insert(JfaPrepassBindGroups {
             jfa_mask_bind_group,
+            jfa_static_mask_bind_group,
+            jfa_static_shift_bind_group,
             jfa_01_bind_group,
             jfa_10_bind_group,
         });
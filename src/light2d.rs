@@ -0,0 +1,357 @@
+use bevy::{
+    ecs::entity::EntityHashMap,
+    prelude::*,
+    render::{
+        render_resource::{BindingResource, BufferUsages, BufferVec, ShaderType},
+        renderer::{RenderDevice, RenderQueue},
+        view::RenderLayers,
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::light_animation2d::{animate_lights2d, LightAnimation2d};
+use crate::mask2d::Mask2dLayers;
+use crate::radiance_cascades::RadianceCascadesConfig;
+
+/// Extracts [`PointLight2d`], [`SpotLight2d`] and [`LineLight2d`] lights into the radiance field.
+///
+/// Lights do not need a mesh or material, rays evaluate them analytically.
+/// Like occluders, they honor [`Mask2dLayers`] and [`RenderLayers`].
+/// Also samples every [`LightAnimation2d`].
+pub struct Light2dPlugin;
+
+impl Plugin for Light2dPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, animate_lights2d);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<Light2dBuffers>()
+            .add_systems(ExtractSchedule, extract_lights2d)
+            .add_systems(Render, prepare_lights2d.in_set(RenderSet::PrepareResources));
+    }
+}
+
+/// Light emitted equally in all directions from a disc.
+#[derive(Component, Clone, Copy)]
+pub struct PointLight2d {
+    pub color: Color,
+    pub intensity: f32,
+    /// Radius of the emitting disc in world units.
+    pub radius: f32,
+    /// Attenuation per world unit travelled, on top of the natural falloff of the cascades.
+    pub falloff: f32,
+}
+
+impl Default for PointLight2d {
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            intensity: 1.0,
+            radius: 8.0,
+            falloff: 0.0,
+        }
+    }
+}
+
+/// Light emitted from a disc into a cone along the local x axis.
+#[derive(Component, Clone, Copy)]
+pub struct SpotLight2d {
+    pub color: Color,
+    pub intensity: f32,
+    /// Radius of the emitting disc in world units.
+    pub radius: f32,
+    /// Full opening angle of the cone in radians.
+    pub angle: f32,
+    /// Attenuation per world unit travelled, on top of the natural falloff of the cascades.
+    pub falloff: f32,
+}
+
+impl Default for SpotLight2d {
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            intensity: 1.0,
+            radius: 8.0,
+            angle: std::f32::consts::FRAC_PI_2,
+            falloff: 0.0,
+        }
+    }
+}
+
+/// Light emitted from a capsule along the local x axis, e.g. a neon tube or a window.
+#[derive(Component, Clone, Copy)]
+pub struct LineLight2d {
+    pub color: Color,
+    pub intensity: f32,
+    /// Length of the line in world units.
+    pub length: f32,
+    /// Radius of the capsule around the line in world units.
+    pub radius: f32,
+    /// Attenuation per world unit travelled, on top of the natural falloff of the cascades.
+    pub falloff: f32,
+}
+
+impl Default for LineLight2d {
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            intensity: 1.0,
+            length: 64.0,
+            radius: 2.0,
+            falloff: 0.0,
+        }
+    }
+}
+
+/// Attach alongside a light to light every pixel directly with exact shadows traced through the
+/// distance field, keeping its shadows crisp regardless of the cascades' angular resolution. The
+/// cascades still see the light as an opaque emitter and carry its light bounced off occluders,
+/// only its direct light is left to the apply pass. Best kept to a few hero lights.
+#[derive(Component, Clone, Copy)]
+pub struct DirectLight2d {
+    /// Sharpness of the shadow penumbra, higher values give harder shadows.
+    pub shadow_sharpness: f32,
+}
+
+impl Default for DirectLight2d {
+    fn default() -> Self {
+        Self {
+            shadow_sharpness: 16.0,
+        }
+    }
+}
+
+/// Light in the pixel space of a view.
+#[derive(ShaderType, Default, Debug, Clone, Copy)]
+pub struct GpuLight2d {
+    /// Color multiplied by intensity.
+    pub color: Vec3,
+    /// One of the `LIGHT2D_*` kinds.
+    pub kind: u32,
+    /// Center of point and spot lights, start of line lights.
+    pub position: Vec2,
+    /// Direction of spot lights, end of line lights.
+    pub direction: Vec2,
+    pub radius: f32,
+    pub falloff: f32,
+    /// Cosine of half the cone angle of spot lights.
+    pub cos_angle: f32,
+    /// Whether the light is evaluated per pixel instead of by the cascades.
+    pub direct: u32,
+    pub shadow_sharpness: f32,
+}
+
+impl GpuLight2d {
+    pub const POINT: u32 = 0;
+    pub const SPOT: u32 = 1;
+    pub const LINE: u32 = 2;
+
+    fn with_direct(mut self, direct: Option<&DirectLight2d>) -> Self {
+        if let Some(direct) = direct {
+            self.direct = 1;
+            self.shadow_sharpness = direct.shadow_sharpness;
+        }
+        self
+    }
+}
+
+/// Lights seen by a view, in its pixel space.
+#[derive(Component, Default, Deref)]
+pub struct ExtractedLights2d(Vec<GpuLight2d>);
+
+/// Light storage buffer of each view.
+#[derive(Resource, Default)]
+pub struct Light2dBuffers(EntityHashMap<BufferVec<GpuLight2d>>);
+
+impl Light2dBuffers {
+    pub fn binding(&self, view_entity: &Entity) -> Option<BindingResource<'_>> {
+        self.0.get(view_entity)?.binding()
+    }
+}
+
+type ExtractedLightQuery<L> = (
+    &'static L,
+    &'static GlobalTransform,
+    &'static InheritedVisibility,
+    Option<&'static Mask2dLayers>,
+    Option<&'static RenderLayers>,
+    Option<&'static DirectLight2d>,
+    Option<&'static LightAnimation2d>,
+);
+
+type ExtractedLightCameraQuery = (
+    Entity,
+    &'static Camera,
+    &'static GlobalTransform,
+    Option<&'static Mask2dLayers>,
+    Option<&'static RenderLayers>,
+);
+
+fn extract_lights2d(
+    mut commands: Commands,
+    q_cameras: Extract<Query<ExtractedLightCameraQuery, With<RadianceCascadesConfig>>>,
+    q_point_lights: Extract<Query<ExtractedLightQuery<PointLight2d>>>,
+    q_spot_lights: Extract<Query<ExtractedLightQuery<SpotLight2d>>>,
+    q_line_lights: Extract<Query<ExtractedLightQuery<LineLight2d>>>,
+) {
+    for (entity, camera, camera_transform, camera_mask_layers, camera_layers) in q_cameras.iter() {
+        let (true, Some(viewport)) = (camera.is_active, camera.physical_viewport_rect()) else {
+            continue;
+        };
+
+        let camera_layers = Mask2dLayers::resolve(camera_mask_layers, camera_layers);
+        let pixel_space = CascadePixelSpace::new(camera, camera_transform, viewport);
+        let to_pixel = |world: Vec3| pixel_space.to_pixel(world);
+        let pixels_per_unit = pixel_space.pixels_per_unit();
+
+        let is_seen = |visibility: &InheritedVisibility,
+                       mask_layers: Option<&Mask2dLayers>,
+                       layers: Option<&RenderLayers>| {
+            visibility.get() && camera_layers.intersects(Mask2dLayers::resolve(mask_layers, layers))
+        };
+
+        let mut lights = Vec::new();
+
+        for (light, transform, visibility, mask_layers, layers, direct, animation) in
+            q_point_lights.iter()
+        {
+            if !is_seen(visibility, mask_layers, layers) {
+                continue;
+            }
+
+            lights.push(
+                GpuLight2d {
+                    color: animated_color(light.color, animation) * light.intensity,
+                    kind: GpuLight2d::POINT,
+                    position: to_pixel(transform.translation()),
+                    radius: light.radius * pixels_per_unit,
+                    falloff: light.falloff / pixels_per_unit,
+                    ..default()
+                }
+                .with_direct(direct),
+            );
+        }
+
+        for (light, transform, visibility, mask_layers, layers, direct, animation) in
+            q_spot_lights.iter()
+        {
+            if !is_seen(visibility, mask_layers, layers) {
+                continue;
+            }
+
+            let position = to_pixel(transform.translation());
+            let direction = to_pixel(transform.translation() + *transform.right()) - position;
+
+            lights.push(
+                GpuLight2d {
+                    color: animated_color(light.color, animation) * light.intensity,
+                    kind: GpuLight2d::SPOT,
+                    position,
+                    direction: direction.normalize_or_zero(),
+                    radius: light.radius * pixels_per_unit,
+                    falloff: light.falloff / pixels_per_unit,
+                    cos_angle: f32::cos(light.angle * 0.5),
+                    ..default()
+                }
+                .with_direct(direct),
+            );
+        }
+
+        for (light, transform, visibility, mask_layers, layers, direct, animation) in
+            q_line_lights.iter()
+        {
+            if !is_seen(visibility, mask_layers, layers) {
+                continue;
+            }
+
+            let half_extent = *transform.right() * light.length * 0.5;
+
+            lights.push(
+                GpuLight2d {
+                    color: animated_color(light.color, animation) * light.intensity,
+                    kind: GpuLight2d::LINE,
+                    position: to_pixel(transform.translation() - half_extent),
+                    direction: to_pixel(transform.translation() + half_extent),
+                    radius: light.radius * pixels_per_unit,
+                    falloff: light.falloff / pixels_per_unit,
+                    ..default()
+                }
+                .with_direct(direct),
+            );
+        }
+
+        commands
+            .get_or_spawn(entity)
+            .insert(ExtractedLights2d(lights));
+    }
+}
+
+/// Maps world space to the pixel space the cascades of a camera are traced in.
+pub(crate) struct CascadePixelSpace {
+    clip_from_world: Mat4,
+    viewport_min: Vec2,
+    viewport_size: Vec2,
+}
+
+impl CascadePixelSpace {
+    pub(crate) fn new(
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+        viewport: URect,
+    ) -> Self {
+        Self {
+            clip_from_world: camera.clip_from_view() * camera_transform.compute_matrix().inverse(),
+            viewport_min: viewport.min.as_vec2(),
+            viewport_size: viewport.size().as_vec2(),
+        }
+    }
+
+    pub(crate) fn to_pixel(&self, world: Vec3) -> Vec2 {
+        let ndc = self.clip_from_world.project_point3(world).xy();
+        self.viewport_min + (ndc * Vec2::new(0.5, -0.5) + 0.5) * self.viewport_size
+    }
+
+    /// Pixel length of a world unit along X.
+    pub(crate) fn pixels_per_unit(&self) -> f32 {
+        self.to_pixel(Vec3::X).distance(self.to_pixel(Vec3::ZERO))
+    }
+}
+
+/// Linear color of a light modulated by its animation.
+fn animated_color(color: Color, animation: Option<&LightAnimation2d>) -> Vec3 {
+    let factor = animation.map_or(LinearRgba::WHITE, LightAnimation2d::factor);
+    color.to_linear().to_vec3() * factor.to_vec3()
+}
+
+fn prepare_lights2d(
+    q_views: Query<(Entity, &ExtractedLights2d)>,
+    mut buffers: ResMut<Light2dBuffers>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    // Remove buffers of views that no longer exist
+    buffers.0.retain(|entity, _| q_views.contains(*entity));
+
+    for (entity, lights) in q_views.iter() {
+        let buffer = buffers.0.entry(entity).or_insert_with(|| {
+            let mut buffer = BufferVec::new(BufferUsages::STORAGE);
+            buffer.set_label(Some("light2d_buffer"));
+            buffer
+        });
+
+        buffer.clear();
+        for light in lights.iter() {
+            buffer.push(*light);
+        }
+        // Storage buffers cannot be empty, a black light contributes nothing
+        if buffer.is_empty() {
+            buffer.push(GpuLight2d::default());
+        }
+
+        buffer.write_buffer(&render_device, &render_queue);
+    }
+}
@@ -0,0 +1,393 @@
+use bevy::prelude::*;
+
+/// Modulates the color of a light ([`PointLight2d`][crate::light2d::PointLight2d],
+/// [`SpotLight2d`][crate::light2d::SpotLight2d], [`LineLight2d`][crate::light2d::LineLight2d])
+/// or the emission of [`Mask2dProperties`][crate::mask2d::Mask2dProperties] over time.
+///
+/// The modulation is applied on extraction, nothing is written back to the entity's components
+/// or materials. Sampling is a pure function of time and [`seed`](Self::seed).
+#[derive(Component, Clone, Debug)]
+pub struct LightAnimation2d {
+    pub mode: LightAnimation2dMode,
+    /// Seed of the flicker noise, animations with the same seed play identically.
+    pub seed: u32,
+    /// Time offset in seconds, to desynchronize otherwise identical animations.
+    pub offset: f32,
+    factor: LinearRgba,
+}
+
+#[derive(Clone, Debug)]
+pub enum LightAnimation2dMode {
+    /// Candle-like flicker from seeded noise.
+    Flicker {
+        /// Noise samples per second.
+        speed: f32,
+        /// How much the intensity dips, from 0 (steady) to 1 (down to black).
+        strength: f32,
+    },
+    /// Smooth sine wave between two intensities.
+    Pulse { frequency: f32, min: f32, max: f32 },
+    /// On and off.
+    Strobe {
+        frequency: f32,
+        /// Fraction of each period the light is on.
+        duty: f32,
+    },
+    /// Linear blend through colors, looping after `period` seconds.
+    ColorCycle { colors: Vec<Color>, period: f32 },
+    /// Looping keyframes of a color multiplier.
+    Curve(Keyframes2d<LinearRgba>),
+}
+
+impl LightAnimation2d {
+    pub fn new(mode: LightAnimation2dMode) -> Self {
+        Self {
+            mode,
+            seed: 0,
+            offset: 0.0,
+            factor: LinearRgba::WHITE,
+        }
+    }
+
+    pub fn flicker(seed: u32) -> Self {
+        Self::new(LightAnimation2dMode::Flicker {
+            speed: 12.0,
+            strength: 0.4,
+        })
+        .with_seed(seed)
+    }
+
+    pub fn pulse(frequency: f32) -> Self {
+        Self::new(LightAnimation2dMode::Pulse {
+            frequency,
+            min: 0.2,
+            max: 1.0,
+        })
+    }
+
+    pub fn strobe(frequency: f32) -> Self {
+        Self::new(LightAnimation2dMode::Strobe {
+            frequency,
+            duty: 0.5,
+        })
+    }
+
+    pub fn color_cycle(colors: impl Into<Vec<Color>>, period: f32) -> Self {
+        Self::new(LightAnimation2dMode::ColorCycle {
+            colors: colors.into(),
+            period,
+        })
+    }
+
+    pub fn curve(keys: impl IntoIterator<Item = (f32, LinearRgba)>) -> Self {
+        Self::new(LightAnimation2dMode::Curve(Keyframes2d::new(keys)))
+    }
+
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: f32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Multiplier sampled by the last [`animate_lights2d`] run.
+    pub fn factor(&self) -> LinearRgba {
+        self.factor
+    }
+
+    /// Multiplier at `time` seconds.
+    pub fn sample(&self, time: f32) -> LinearRgba {
+        let time = time + self.offset;
+
+        match &self.mode {
+            LightAnimation2dMode::Flicker { speed, strength } => {
+                // Two octaves of value noise for a less regular flicker
+                let t = time * speed;
+                let noise =
+                    value_noise(self.seed, t) * 0.7 + value_noise(!self.seed, t * 2.3) * 0.3;
+                gray(1.0 - strength.clamp(0.0, 1.0) * noise)
+            }
+            LightAnimation2dMode::Pulse {
+                frequency,
+                min,
+                max,
+            } => {
+                let wave = f32::sin(time * frequency * std::f32::consts::TAU) * 0.5 + 0.5;
+                gray(min + (max - min) * wave)
+            }
+            LightAnimation2dMode::Strobe { frequency, duty } => {
+                match (time * frequency).rem_euclid(1.0) < *duty {
+                    true => LinearRgba::WHITE,
+                    false => LinearRgba::BLACK,
+                }
+            }
+            LightAnimation2dMode::ColorCycle { colors, period } => {
+                if colors.is_empty() {
+                    return LinearRgba::WHITE;
+                }
+
+                let phase = repeat(time, *period) / period.max(f32::EPSILON) * colors.len() as f32;
+                let from = (phase.floor() as usize).min(colors.len() - 1);
+                let to = (from + 1) % colors.len();
+
+                colors[from]
+                    .to_linear()
+                    .mix(&colors[to].to_linear(), phase.fract())
+            }
+            LightAnimation2dMode::Curve(keyframes) => keyframes.sample(time),
+        }
+    }
+}
+
+/// `(time in seconds, value)` keys, linearly interpolated and looping after the last key.
+///
+/// Shared by [`LightAnimation2dMode::Curve`] and
+/// [`EmissionCurve2d`][crate::emissive2d::EmissionCurve2d], which samples it the same way on the
+/// GPU. Before the first key the curve holds its value, an empty curve is a constant `default`.
+#[derive(Debug, Clone)]
+pub struct Keyframes2d<T> {
+    /// Sorted by time.
+    keys: Vec<(f32, T)>,
+}
+
+impl<T> Default for Keyframes2d<T> {
+    fn default() -> Self {
+        Self { keys: Vec::new() }
+    }
+}
+
+impl<T> Keyframes2d<T> {
+    /// Keyframes from keys in any order.
+    pub fn new(keys: impl IntoIterator<Item = (f32, T)>) -> Self {
+        let mut keys = keys.into_iter().collect::<Vec<_>>();
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    /// Keys sorted by time.
+    pub fn keys(&self) -> &[(f32, T)] {
+        &self.keys
+    }
+
+    /// Keeps the first `len` keys.
+    pub fn truncate(&mut self, len: usize) {
+        self.keys.truncate(len);
+    }
+
+    /// Interpolated value at `time` seconds, `default` if there is no key.
+    pub fn sample_or(&self, time: f32, default: T) -> T
+    where
+        T: Keyframe2d,
+    {
+        let (Some(&first), Some(&last)) = (self.keys.first(), self.keys.last()) else {
+            return default;
+        };
+
+        let t = repeat(time, last.0);
+        if t <= first.0 {
+            return first.1;
+        }
+
+        for window in self.keys.windows(2) {
+            let ((start, from), (end, to)) = (window[0], window[1]);
+            if t <= end {
+                let s = (t - start) / (end - start).max(f32::EPSILON);
+                return from.lerp(to, s);
+            }
+        }
+
+        last.1
+    }
+}
+
+/// Value [`Keyframes2d`] interpolates between.
+pub trait Keyframe2d: Copy {
+    fn lerp(self, to: Self, s: f32) -> Self;
+}
+
+impl Keyframe2d for f32 {
+    fn lerp(self, to: Self, s: f32) -> Self {
+        self + (to - self) * s
+    }
+}
+
+impl Keyframe2d for LinearRgba {
+    fn lerp(self, to: Self, s: f32) -> Self {
+        self.mix(&to, s)
+    }
+}
+
+impl Keyframes2d<LinearRgba> {
+    /// Interpolated multiplier at `time` seconds, white if there is no key.
+    pub fn sample(&self, time: f32) -> LinearRgba {
+        self.sample_or(time, LinearRgba::WHITE)
+    }
+}
+
+/// Samples every [`LightAnimation2d`] at the current time.
+pub fn animate_lights2d(mut q_animations: Query<&mut LightAnimation2d>, time: Res<Time>) {
+    let elapsed = time.elapsed_seconds();
+
+    for mut animation in q_animations.iter_mut() {
+        animation.factor = animation.sample(elapsed);
+    }
+}
+
+fn gray(value: f32) -> LinearRgba {
+    LinearRgba::rgb(value, value, value)
+}
+
+fn repeat(time: f32, period: f32) -> f32 {
+    match period > 0.0 {
+        true => time.rem_euclid(period),
+        false => 0.0,
+    }
+}
+
+/// Smoothly interpolated noise in [0, 1) over hashed integer lattice points.
+fn value_noise(seed: u32, t: f32) -> f32 {
+    let cell = t.floor();
+    let (a, b) = (hash(seed, cell as i32), hash(seed, cell as i32 + 1));
+    let x = t - cell;
+
+    a + (b - a) * x * x * (3.0 - 2.0 * x)
+}
+
+/// Deterministic hash of a lattice point into [0, 1).
+fn hash(seed: u32, x: i32) -> f32 {
+    // PCG output permutation
+    let mut state = (x as u32)
+        .wrapping_mul(747796405)
+        .wrapping_add(seed.wrapping_mul(2891336453))
+        .wrapping_add(2891336453);
+    state = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    state = (state >> 22) ^ state;
+
+    (state >> 8) as f32 / (1u32 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rgb(color: LinearRgba, expected: [f32; 3]) {
+        let rgb = [color.red, color.green, color.blue];
+        for (value, expected) in rgb.into_iter().zip(expected) {
+            assert!(
+                (value - expected).abs() < 1e-5,
+                "sampled {rgb:?}, expected {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn flicker_is_deterministic_per_seed() {
+        let flicker = LightAnimation2d::flicker(1);
+        assert_rgb(flicker.sample(0.37), [0.852_541_1; 3]);
+        assert_rgb(flicker.sample(1.0), [0.801_663_46; 3]);
+
+        // Resampling gives the same value
+        assert_eq!(flicker.sample(0.37), flicker.sample(0.37));
+    }
+
+    #[test]
+    fn flicker_diverges_across_seeds() {
+        let times = [0.0, 0.1, 0.37, 1.0];
+        let samples = |seed| times.map(|t| LightAnimation2d::flicker(seed).sample(t).red);
+
+        assert_ne!(samples(0), samples(1));
+        assert_ne!(samples(1), samples(7));
+        assert_ne!(samples(0), samples(7));
+    }
+
+    #[test]
+    fn flicker_stays_within_strength() {
+        let flicker = LightAnimation2d::new(LightAnimation2dMode::Flicker {
+            speed: 12.0,
+            strength: 0.4,
+        });
+
+        for i in 0..1000 {
+            let value = flicker.sample(i as f32 * 0.013).red;
+            assert!((0.6..=1.0).contains(&value), "sampled {value}");
+        }
+    }
+
+    #[test]
+    fn pulse_follows_sine() {
+        let pulse = LightAnimation2d::pulse(2.0);
+        assert_rgb(pulse.sample(0.0), [0.6; 3]);
+        assert_rgb(pulse.sample(0.125), [1.0; 3]);
+        assert_rgb(pulse.sample(0.375), [0.2; 3]);
+        assert_rgb(pulse.sample(0.5), [0.6; 3]);
+    }
+
+    #[test]
+    fn strobe_respects_duty() {
+        let strobe = LightAnimation2d::new(LightAnimation2dMode::Strobe {
+            frequency: 2.0,
+            duty: 0.25,
+        });
+        assert_rgb(strobe.sample(0.1), [1.0; 3]);
+        assert_rgb(strobe.sample(0.2), [0.0; 3]);
+        assert_rgb(strobe.sample(0.6), [1.0; 3]);
+        assert_rgb(strobe.sample(-0.3), [0.0; 3]);
+    }
+
+    #[test]
+    fn color_cycle_blends_and_loops() {
+        let cycle = LightAnimation2d::color_cycle(
+            [
+                Color::LinearRgba(LinearRgba::RED),
+                Color::LinearRgba(LinearRgba::BLUE),
+            ],
+            2.0,
+        );
+        assert_rgb(cycle.sample(0.0), [1.0, 0.0, 0.0]);
+        assert_rgb(cycle.sample(0.5), [0.5, 0.0, 0.5]);
+        assert_rgb(cycle.sample(1.0), [0.0, 0.0, 1.0]);
+        // Blends back into the first color
+        assert_rgb(cycle.sample(1.5), [0.5, 0.0, 0.5]);
+        assert_rgb(cycle.sample(2.5), [0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn curve_interpolates_sorted_keys() {
+        let curve = LightAnimation2d::curve([
+            (2.0, LinearRgba::rgb(1.0, 0.0, 0.0)),
+            (1.0, LinearRgba::rgb(0.0, 1.0, 0.0)),
+        ]);
+        assert_rgb(curve.sample(0.5), [0.0, 1.0, 0.0]);
+        assert_rgb(curve.sample(1.25), [0.25, 0.75, 0.0]);
+        assert_rgb(curve.sample(3.5), [0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn emission_curve_keeps_the_earliest_keys() {
+        let curve = crate::emissive2d::EmissionCurve2d::new(
+            (0..10).rev().map(|i| (i as f32 + 1.0, i as f32)),
+        );
+        assert_eq!(
+            curve.keys().len(),
+            crate::emissive2d::EmissionCurve2d::MAX_KEYS
+        );
+        assert_eq!(curve.sample(0.5), 0.0);
+        assert_eq!(curve.sample(2.5), 1.5);
+        assert_eq!(
+            crate::emissive2d::EmissionCurve2d::default().sample(1.0),
+            1.0
+        );
+    }
+
+    #[test]
+    fn offset_shifts_time() {
+        let pulse = LightAnimation2d::pulse(2.0);
+        assert_eq!(
+            pulse.clone().with_offset(0.125).sample(0.0),
+            pulse.sample(0.125)
+        );
+    }
+}
@@ -2,15 +2,20 @@ use bevy::{
     // color::palettes::css,
     core_pipeline::{bloom::BloomSettings, smaa::SmaaSettings, tonemapping::Tonemapping},
     prelude::*,
-    sprite::Mesh2dHandle,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
     window::PrimaryWindow,
 };
 use bevy_motiongfx::{prelude::*, BevyMotionGfxPlugin};
 // use rand::prelude::*;
 
 mod debug_render_pipeline;
+mod emissive2d;
+mod fog2d;
 mod jfa;
+mod light2d;
+mod light_animation2d;
 mod mask2d;
+mod mask2d_sprite;
 mod math_util;
 mod radiance_cascades;
 
@@ -19,7 +24,10 @@ fn main() {
         .add_plugins(DefaultPlugins)
         .add_plugins(BevyMotionGfxPlugin)
         .insert_resource(Msaa::Off)
-        .add_plugins(mask2d::Mask2dPrepassPlugin::<ColorMaterial>::default())
+        .add_plugins(mask2d::Mask2dPrepassPlugin)
+        .add_plugins(mask2d::Mask2dMaterialPlugin::<ColorMaterial>::default())
+        .add_plugins(mask2d_sprite::Mask2dSpritePlugin)
+        .add_plugins(emissive2d::EmissiveMaterial2dPlugin)
         .add_plugins(jfa::JfaPrepassPlugin)
         .add_plugins(radiance_cascades::RadianceCascadesPlugin)
         // .add_plugins(debug_render_pipeline::DebugRenderPipelinePlugin)
@@ -37,6 +45,7 @@ fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    mut emissive_materials: ResMut<Assets<emissive2d::EmissiveMaterial2d>>,
     // asset_server: Res<AssetServer>,
 ) {
     // camera
@@ -54,7 +63,13 @@ fn setup(
         },
         jfa::JfaPrepass,
         mask2d::Mask2dPrepass,
+        mask2d::Mask2dMultisample::default(),
         radiance_cascades::RadianceCascadesConfig::default(),
+        fog2d::Fog2d {
+            density: 0.001,
+            ambient: Color::linear_rgb(0.01, 0.01, 0.015),
+            ..default()
+        },
         BloomSettings::default(),
         SmaaSettings::default(),
     ));
@@ -79,6 +94,7 @@ fn setup(
                     ..default()
                 },
                 mask2d::Mask2d,
+                mask2d::StaticMask2d,
             ));
         }
     }
@@ -106,6 +122,45 @@ fn setup(
         Marked,
     ));
 
+    // Spot light
+    commands.spawn((
+        SpatialBundle::from_transform(
+            Transform::from_xyz(-SPACING * COUNT as f32 * 0.5, 0.0, 0.0)
+                .with_rotation(Quat::from_rotation_z(-0.3)),
+        ),
+        light2d::SpotLight2d {
+            color: Color::linear_rgb(1.0, 0.6, 0.3),
+            intensity: 2.0,
+            ..default()
+        },
+        light2d::DirectLight2d::default(),
+        light_animation2d::LightAnimation2d::flicker(7),
+    ));
+
+    // Flickering neon tube
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: Mesh2dHandle(meshes.add(Rectangle::new(120.0, 6.0))),
+            material: emissive_materials.add(emissive2d::EmissiveMaterial2d {
+                color: Color::linear_rgb(0.8, 0.2, 1.0),
+                emission: Color::linear_rgb(0.8, 0.2, 1.0),
+                emission_intensity: 3.0,
+                emission_curve: emissive2d::EmissionCurve2d::new([
+                    (0.0, 1.0),
+                    (1.6, 1.0),
+                    (1.65, 0.1),
+                    (1.7, 1.0),
+                    (1.8, 0.2),
+                    (2.0, 1.0),
+                ]),
+                ..default()
+            }),
+            transform: Transform::from_xyz(0.0, SPACING * COUNT as f32 * 0.5, 0.1),
+            ..default()
+        },
+        mask2d::Mask2d,
+    ));
+
     // Rect
     {
         const MAX: f32 = SPACING * COUNT as f32 * 0.55;
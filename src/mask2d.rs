@@ -1,15 +1,28 @@
 use std::{hash::Hash, marker::PhantomData, ops::Range};
 
 use bevy::{
-    core_pipeline::core_2d::graph::{Core2d, Node2d},
-    ecs::{entity::EntityHashSet, query::QueryItem},
+    asset::UntypedAssetId,
+    core_pipeline::{
+        core_2d::graph::{Core2d, Node2d},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::{
+        entity::{EntityHashMap, EntityHashSet},
+        query::QueryItem,
+        system::{lifetimeless::SRes, SystemParamItem},
+    },
     prelude::*,
     render::{
-        batching::no_gpu_preprocessing::batch_and_prepare_sorted_render_phase,
+        batching::{
+            no_gpu_preprocessing::batch_and_prepare_sorted_render_phase, GetBatchData,
+            NoAutomaticBatching,
+        },
         camera::ExtractedCamera,
         diagnostic::RecordDiagnostics,
         extract_component::{ExtractComponent, ExtractComponentPlugin},
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
         mesh::{GpuMesh, MeshVertexBufferLayoutRef},
+        primitives::{Aabb, Frustum},
         render_asset::{prepare_assets, RenderAssets},
         render_graph::{
             NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
@@ -20,41 +33,232 @@ use bevy::{
             ViewSortedRenderPhases,
         },
         render_resource::{
-            CachedRenderPipelineId, ColorTargetState, ColorWrites, PipelineCache,
-            RenderPassColorAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
+            binding_types::texture_2d_multisampled, BindGroupEntries, BindGroupLayout,
+            BindGroupLayoutEntries, BindingResource, BufferUsages, BufferVec,
+            CachedRenderPipelineId, ColorTargetState, ColorWrites, FragmentState, LoadOp,
+            Operations, PipelineCache, RenderPassColorAttachment, RenderPassDescriptor,
+            RenderPipelineDescriptor, ShaderDefVal, ShaderRef, ShaderStages, ShaderType,
             SpecializedMeshPipeline, SpecializedMeshPipelineError, SpecializedMeshPipelines,
-            TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+            StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+            TextureUsages,
         },
-        renderer::{RenderContext, RenderDevice},
+        renderer::{RenderAdapter, RenderContext, RenderDevice, RenderQueue},
         texture::{CachedTexture, TextureCache},
-        view::{ExtractedView, ViewTarget, VisibleEntities},
+        view::{
+            ExtractedView, NoFrustumCulling, RenderLayers, ViewTarget, VisibilitySystems,
+            VisibleEntities, DEFAULT_LAYERS,
+        },
         Extract, Render, RenderApp, RenderSet,
     },
     sprite::{
-        DrawMesh2d, Material2d, Material2dKey, Material2dPipeline, Mesh2dPipeline,
-        Mesh2dPipelineKey, PreparedMaterial2d, RenderMaterial2dInstances, RenderMesh2dInstances,
-        SetMaterial2dBindGroup, SetMesh2dBindGroup, SetMesh2dViewBindGroup, WithMesh2d,
+        extract_mesh2d, DrawMesh2d, Material2d, Material2dBindGroupId, Material2dKey,
+        Material2dPipeline, Mesh2d, Mesh2dHandle, Mesh2dPipeline, Mesh2dPipelineKey,
+        Mesh2dTransforms, Mesh2dUniform, MeshFlags, PreparedMaterial2d, RenderMaterial2dInstances,
+        RenderMesh2dInstance, RenderMesh2dInstances, SetMaterial2dBindGroup, SetMesh2dBindGroup,
+        SetMesh2dViewBindGroup, WithMesh2d, WithSprite,
     },
 };
 
+use crate::light_animation2d::LightAnimation2d;
+
 /// Attach to entities.
 #[derive(Component, ExtractComponent, Clone, Copy)]
 pub struct Mask2d;
 
+/// Attach alongside [`Mask2d`] to occluders that rarely move (e.g. level geometry).
+///
+/// Static occluders are rasterized into a separate mask layer whose jump flood
+/// is cached and only regenerated when a static occluder or the view changes.
+#[derive(Component, ExtractComponent, Clone, Copy)]
+pub struct StaticMask2d;
+
+/// Alpha below which texels of a textured occluder are left out of the mask.
+///
+/// Meshes only sample their texture when this is attached and their material implements
+/// [`Mask2dMaterial::mask_texture_alpha`]. Sprites always do, using the default cutoff if absent.
+#[derive(Component, ExtractComponent, Clone, Copy)]
+pub struct Mask2dAlphaCutoff(pub f32);
+
+impl Mask2dAlphaCutoff {
+    /// Cutoff quantized to 8 bits so that it can be used as a pipeline key.
+    fn quantize(&self) -> u8 {
+        (self.0.clamp(0.0, 1.0) * 255.0).round() as u8
+    }
+}
+
+impl Default for Mask2dAlphaCutoff {
+    fn default() -> Self {
+        Self(0.5)
+    }
+}
+
+/// Lighting properties of a [`Mask2d`] occluder, independent of the colors it is rendered with.
+///
+/// The emission can be animated with a [`LightAnimation2d`].
+///
+/// Rays crossing the occluder keep `mix(1, transmission, opacity)` of the light behind it per
+/// channel, further scaled by the texel opacity of the mask prepass. Rays march on through
+/// translucent occluders, each crossing multiplies its transmittance in.
+#[derive(Component, Clone, Copy)]
+pub struct Mask2dProperties {
+    /// Color of the light emitted by the occluder.
+    pub emission: Color,
+    /// Multiplier of the emission color.
+    pub emission_intensity: f32,
+    /// How much of the occluder is covered by its [`transmission`](Self::transmission), from 0
+    /// (transparent) to 1.
+    pub opacity: f32,
+    /// Tint of the light passing through the covered part of the occluder, black for opaque
+    /// occluders and e.g. a color for stained glass.
+    pub transmission: Color,
+    /// Color of the light reflected by the occluder's surface.
+    pub albedo: Color,
+    /// Fraction of the light hitting the occluder that is absorbed instead of reflected, tinted
+    /// by the albedo, onto its surroundings. Reflected light lags a frame behind.
+    pub absorption: f32,
+}
+
+impl Default for Mask2dProperties {
+    fn default() -> Self {
+        Self {
+            emission: Color::BLACK,
+            emission_intensity: 1.0,
+            opacity: 1.0,
+            transmission: Color::BLACK,
+            albedo: Color::WHITE,
+            absorption: 1.0,
+        }
+    }
+}
+
+/// Which passes a [`Mask2d`] entity is drawn into.
+#[derive(Component, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mask2dVisibility {
+    /// Drawn into both the main pass and the mask prepass.
+    #[default]
+    Both,
+    /// Only drawn into the mask prepass, e.g. invisible shadow casters or light sources.
+    /// Use [`Mask2dProperties`] to make it emit light.
+    MaskOnly,
+    /// Only drawn into the main pass, leaving the object out of GI.
+    MainOnly,
+}
+
+/// Render layers used by the mask prepass in place of [`RenderLayers`].
+///
+/// On a [`Mask2d`] entity, decides which [`Mask2dPrepass`] cameras it occludes for. On a camera,
+/// decides which [`Mask2d`] entities its GI sees. An entity on render layers no camera renders
+/// can still occlude through its mask layers.
+#[derive(Component, Clone, Default, Deref, DerefMut)]
+pub struct Mask2dLayers(pub RenderLayers);
+
+impl Mask2dLayers {
+    /// Layers seen by the mask prepass for an entity with the given components.
+    pub fn resolve<'a>(
+        mask_layers: Option<&'a Mask2dLayers>,
+        layers: Option<&'a RenderLayers>,
+    ) -> &'a RenderLayers {
+        mask_layers
+            .map(|layers| &layers.0)
+            .or(layers)
+            .unwrap_or(DEFAULT_LAYERS)
+    }
+}
+
+/// [`Mask2d`] entities seen by the mask prepass of each [`Mask2dPrepass`] camera.
+#[derive(Resource, ExtractResource, Default, Clone, Deref)]
+pub struct Mask2dVisibleEntities(EntityHashMap<Vec<Entity>>);
+
+impl Mask2dVisibleEntities {
+    /// Entities seen by the mask prepass of any camera, once each.
+    pub fn unique(&self) -> EntityHashSet {
+        self.0.values().flatten().copied().collect()
+    }
+}
+
+/// Set for the frame in which any [`StaticMask2d`] entity was added, removed or changed.
+#[derive(Resource, ExtractResource, Default, Clone, Copy)]
+pub struct StaticMask2dDirty(pub bool);
+
 /// Attach to camera.
 #[derive(Component, ExtractComponent, Clone, Copy)]
 pub struct Mask2dPrepass;
 
-#[derive(Default)]
-pub struct Mask2dPrepassPlugin<M: Material2d>(PhantomData<M>);
+/// Attach to a [`Mask2dPrepass`] camera to rasterize occluders with this many samples per pixel.
+///
+/// The samples are resolved into the fractional [coverage](Mask2dPrepassTexture::get_coverage)
+/// of each pixel, which smooths the shadow edges of curved occluders. A count of 4 is supported
+/// on every platform, others depend on the adapter and are lowered to the closest supported count
+/// on extraction.
+#[derive(Component, Clone, Copy)]
+pub struct Mask2dMultisample(pub u32);
+
+impl Mask2dMultisample {
+    /// Sample count of the mask prepass of a camera with the given component.
+    pub fn samples(multisample: Option<&Mask2dMultisample>) -> u32 {
+        multisample.map_or(1, |multisample| multisample.0.max(1))
+    }
+}
 
-impl<M: Material2d> Plugin for Mask2dPrepassPlugin<M>
-where
-    M::Data: PartialEq + Eq + Hash + Clone,
-{
+impl Default for Mask2dMultisample {
+    fn default() -> Self {
+        Self(4)
+    }
+}
+
+/// Materials that can be rendered into the mask prepass.
+pub trait Mask2dMaterial: Material2d {
+    /// Returns this material's mask prepass fragment shader. If [`ShaderRef::Default`] is
+    /// returned, the default `shaders/mask2d.wgsl` is used.
+    ///
+    /// Custom shaders take a `Mask2dVertexOutput` and should return `Mask2dOutput`, both from
+    /// `shaders/mask2d_types.wgsl`. The output carries the occluder's object index alongside
+    /// additional emission and opacity. Discarded fragments do not occlude.
+    fn mask_fragment_shader() -> ShaderRef {
+        ShaderRef::Default
+    }
+
+    /// Whether the material binds a texture at `@binding(1)` and its sampler at `@binding(2)`
+    /// (like [`ColorMaterial`] does). Texels of that texture with an alpha below
+    /// [`Mask2dAlphaCutoff`] are then left out of the mask of entities carrying it.
+    fn mask_texture_alpha() -> bool {
+        false
+    }
+}
+
+impl Mask2dMaterial for ColorMaterial {
+    fn mask_texture_alpha() -> bool {
+        true
+    }
+}
+
+/// Sets up the mask prepass phase, render graph node and textures shared by every
+/// [`Mask2dMaterialPlugin`] and [`Mask2dSpritePlugin`][crate::mask2d_sprite::Mask2dSpritePlugin].
+///
+/// [`Mask2d`] meshes are drawn regardless of their material by a single occluder pipeline,
+/// unless a [`Mask2dMaterialPlugin`] needs their material for alpha or custom masks.
+pub struct Mask2dPrepassPlugin;
+
+impl Plugin for Mask2dPrepassPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(ExtractComponentPlugin::<Mask2dPrepass>::default())
-            .add_plugins(ExtractComponentPlugin::<Mask2d>::default());
+        app.init_resource::<StaticMask2dDirty>()
+            .init_resource::<Mask2dVisibleEntities>()
+            .add_plugins(ExtractComponentPlugin::<Mask2dPrepass>::default())
+            .add_plugins(ExtractComponentPlugin::<Mask2d>::default())
+            .add_plugins(ExtractComponentPlugin::<StaticMask2d>::default())
+            .add_plugins(ExtractComponentPlugin::<Mask2dAlphaCutoff>::default())
+            .add_plugins(ExtractResourcePlugin::<StaticMask2dDirty>::default())
+            .add_plugins(ExtractResourcePlugin::<Mask2dVisibleEntities>::default())
+            .add_systems(
+                PostUpdate,
+                (
+                    (check_mask2d_visibility, hide_mask_only_mask2d)
+                        .after(VisibilitySystems::CheckVisibility),
+                    detect_static_mask2d_changes
+                        .after(TransformSystem::TransformPropagate)
+                        .after(VisibilitySystems::VisibilityPropagate),
+                ),
+            );
 
         let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
             return;
@@ -62,21 +266,31 @@ where
 
         render_app
             .init_resource::<ViewSortedRenderPhases<Mask2dItem>>()
-            .add_systems(ExtractSchedule, extract_core_2d_camera_phases);
-
-        render_app
             .init_resource::<DrawFunctions<Mask2dItem>>()
-            .init_resource::<SpecializedMeshPipelines<Mask2dPrepassPipeline<M>>>()
-            .add_render_command::<Mask2dItem, DrawMaterial2d<M>>()
+            .init_resource::<Mask2dMaterialMeshes>()
+            .init_resource::<Mask2dObjects>()
+            .init_resource::<SpecializedMeshPipelines<Mask2dMeshPipeline>>()
+            .add_render_command::<Mask2dItem, DrawMask2dMesh>()
+            .add_systems(
+                ExtractSchedule,
+                (
+                    extract_core_2d_camera_phases,
+                    extract_mask2d_multisample,
+                    extract_mask2d_objects,
+                    extract_mask_only_mesh2d.after(extract_mesh2d),
+                    clear_mask2d_material_meshes,
+                ),
+            )
             .add_systems(
                 Render,
                 (
-                    batch_and_prepare_sorted_render_phase::<Mask2dItem, Mesh2dPipeline>
-                        .in_set(RenderSet::PrepareResources),
-                    queue_mask2d_meshes::<M>
+                    queue_mask2d_occluders
                         .in_set(RenderSet::QueueMeshes)
-                        .after(prepare_assets::<PreparedMaterial2d<M>>),
+                        .after(QueueMask2dMaterialMeshes),
+                    batch_and_prepare_sorted_render_phase::<Mask2dItem, Mask2dMeshPipeline>
+                        .in_set(RenderSet::PrepareResources),
                     prepare_mask2d_texture.in_set(RenderSet::PrepareResources),
+                    prepare_mask2d_objects.in_set(RenderSet::PrepareResources),
                 ),
             );
 
@@ -85,6 +299,51 @@ where
             .add_render_graph_edges(Core2d, (Mask2dPrepassLabel, Node2d::EndMainPass));
     }
 
+    fn finish(&self, app: &mut App) {
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<Mask2dMeshPipeline>()
+                .init_resource::<Mask2dResolvePipeline>()
+                .init_resource::<Mask2dSampleCounts>();
+        }
+    }
+}
+
+/// Renders [`Mask2d`] meshes that uses the material `M` into the mask prepass, for materials with
+/// a [custom mask shader](Mask2dMaterial::mask_fragment_shader) or
+/// [texture alpha](Mask2dMaterial::mask_texture_alpha).
+///
+/// Can be added once per material type, [`Mask2dPrepassPlugin`] is added along if needed.
+#[derive(Default)]
+pub struct Mask2dMaterialPlugin<M: Mask2dMaterial>(PhantomData<M>);
+
+impl<M: Mask2dMaterial> Plugin for Mask2dMaterialPlugin<M>
+where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<Mask2dPrepassPlugin>() {
+            app.add_plugins(Mask2dPrepassPlugin);
+        }
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<SpecializedMeshPipelines<Mask2dPrepassPipeline<M>>>()
+            .init_resource::<Mask2dMaterialInstances<M>>()
+            .add_render_command::<Mask2dItem, DrawMaterial2d<M>>()
+            .add_systems(ExtractSchedule, extract_mask_only_materials::<M>)
+            .add_systems(
+                Render,
+                queue_mask2d_meshes::<M>
+                    .in_set(RenderSet::QueueMeshes)
+                    .in_set(QueueMask2dMaterialMeshes)
+                    .after(prepare_assets::<PreparedMaterial2d<M>>),
+            );
+    }
+
     fn finish(&self, app: &mut App) {
         if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
             render_app.init_resource::<Mask2dPrepassPipeline<M>>();
@@ -92,6 +351,83 @@ where
     }
 }
 
+/// Systems queuing [`Mask2d`] meshes through their material, before the rest is queued by the
+/// occluder pipeline.
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct QueueMask2dMaterialMeshes;
+
+/// [`Mask2d`] meshes queued through their material this frame.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct Mask2dMaterialMeshes(EntityHashSet);
+
+/// Materials of [`Mask2d`] meshes that are hidden from every camera's main pass, and therefore
+/// left out of [`RenderMaterial2dInstances`].
+#[derive(Resource, Deref, DerefMut)]
+pub struct Mask2dMaterialInstances<M: Material2d>(EntityHashMap<AssetId<M>>);
+
+impl<M: Material2d> Default for Mask2dMaterialInstances<M> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+/// Object index of every visible [`Mask2d`] entity and the [`Mask2dProperties`] table it indexes.
+///
+/// The index is written into the mask textures, 0 being empty space.
+#[derive(Resource)]
+pub struct Mask2dObjects {
+    indices: EntityHashMap<u32>,
+    properties: BufferVec<GpuMask2dProperties>,
+}
+
+impl Mask2dObjects {
+    /// Occluders beyond this count are left out of the mask.
+    pub const MAX_OBJECTS: usize = u16::MAX as usize;
+
+    /// Object index of a [`Mask2d`] entity.
+    pub fn get(&self, entity: &Entity) -> Option<u32> {
+        self.indices.get(entity).copied()
+    }
+
+    /// Storage buffer of [`GpuMask2dProperties`] indexed by object index.
+    pub fn binding(&self) -> Option<BindingResource<'_>> {
+        self.properties.binding()
+    }
+}
+
+impl Default for Mask2dObjects {
+    fn default() -> Self {
+        let mut properties = BufferVec::new(BufferUsages::STORAGE);
+        properties.set_label(Some("mask2d_properties"));
+
+        Self {
+            indices: EntityHashMap::default(),
+            properties,
+        }
+    }
+}
+
+#[derive(ShaderType, Debug, Clone, Copy)]
+pub struct GpuMask2dProperties {
+    pub emission: Vec3,
+    pub opacity: f32,
+    pub albedo: Vec3,
+    pub absorption: f32,
+    pub transmission: Vec3,
+}
+
+impl From<&Mask2dProperties> for GpuMask2dProperties {
+    fn from(properties: &Mask2dProperties) -> Self {
+        Self {
+            emission: properties.emission.to_linear().to_vec3() * properties.emission_intensity,
+            opacity: properties.opacity.clamp(0.0, 1.0),
+            albedo: properties.albedo.to_linear().to_vec3(),
+            absorption: properties.absorption.clamp(0.0, 1.0),
+            transmission: properties.transmission.to_linear().to_vec3(),
+        }
+    }
+}
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
 pub struct Mask2dPrepassLabel;
 
@@ -117,6 +453,23 @@ impl ViewNode for Mask2dPrepassNode {
             return Ok(());
         };
 
+        // Multisampled masks are rendered aside and resolved afterwards, emission resolves itself
+        let (mask_view, static_mask_view, emission_view, emission_resolve_target) =
+            match &texture.multisampled {
+                Some(multisampled) => (
+                    &multisampled.mask_texture.default_view,
+                    &multisampled.static_mask_texture.default_view,
+                    &multisampled.emission_texture.default_view,
+                    Some(&*texture.emission_texture.default_view),
+                ),
+                None => (
+                    &texture.mask_texture.default_view,
+                    &texture.static_mask_texture.default_view,
+                    &texture.emission_texture.default_view,
+                    None,
+                ),
+            };
+
         // This needs to run at least once to clear the background color, even if there are no items to render
         {
             #[cfg(feature = "trace")]
@@ -126,11 +479,23 @@ impl ViewNode for Mask2dPrepassNode {
 
             let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
                 label: Some("mask_pass_2d"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &texture.get().default_view,
-                    resolve_target: None,
-                    ops: default(),
-                })],
+                color_attachments: &[
+                    Some(RenderPassColorAttachment {
+                        view: mask_view,
+                        resolve_target: None,
+                        ops: default(),
+                    }),
+                    Some(RenderPassColorAttachment {
+                        view: static_mask_view,
+                        resolve_target: None,
+                        ops: default(),
+                    }),
+                    Some(RenderPassColorAttachment {
+                        view: emission_view,
+                        resolve_target: emission_resolve_target,
+                        ops: default(),
+                    }),
+                ],
                 depth_stencil_attachment: None,
                 timestamp_writes: None,
                 occlusion_query_set: None,
@@ -149,6 +514,88 @@ impl ViewNode for Mask2dPrepassNode {
             pass_span.end(&mut render_pass);
         }
 
+        let resolve_pipeline = world.resource::<Mask2dResolvePipeline>();
+        // Decided on prepare, so that the static mask is not cached while left empty
+        let multisampled = texture.multisampled.as_ref().filter(|_| texture.resolved);
+        let resolve = multisampled.and_then(|multisampled| {
+            let pipeline = world
+                .resource::<PipelineCache>()
+                .get_render_pipeline(resolve_pipeline.pipeline)?;
+            Some((multisampled, pipeline))
+        });
+
+        match resolve {
+            Some((multisampled, pipeline)) => {
+                let bind_group = render_context.render_device().create_bind_group(
+                    "mask2d_resolve_bind_group",
+                    &resolve_pipeline.layout,
+                    &BindGroupEntries::sequential((
+                        &multisampled.mask_texture.default_view,
+                        &multisampled.static_mask_texture.default_view,
+                    )),
+                );
+
+                let mut render_pass =
+                    render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                        label: Some("mask2d_resolve_pass"),
+                        color_attachments: &[
+                            Some(RenderPassColorAttachment {
+                                view: &texture.get().default_view,
+                                resolve_target: None,
+                                ops: default(),
+                            }),
+                            Some(RenderPassColorAttachment {
+                                view: &texture.get_static().default_view,
+                                resolve_target: None,
+                                ops: default(),
+                            }),
+                            Some(RenderPassColorAttachment {
+                                view: &texture.get_coverage().default_view,
+                                resolve_target: None,
+                                ops: default(),
+                            }),
+                        ],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+
+                render_pass.set_render_pipeline(pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            }
+            None => {
+                // Single sampled occluders cover their pixels entirely
+                let coverage_attachment = Some(RenderPassColorAttachment {
+                    view: &texture.get_coverage().default_view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(LinearRgba::WHITE.into()),
+                        store: StoreOp::Store,
+                    },
+                });
+                // Multisampled occluders are left out of the masks until they can be resolved
+                let mut color_attachments = vec![coverage_attachment];
+                if texture.multisampled.is_some() {
+                    for mask in [texture.get(), texture.get_static()] {
+                        color_attachments.push(Some(RenderPassColorAttachment {
+                            view: &mask.default_view,
+                            resolve_target: None,
+                            ops: default(),
+                        }));
+                    }
+                }
+
+                render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                    label: Some("mask2d_coverage_clear_pass"),
+                    color_attachments: &color_attachments,
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+            }
+        }
+
         // WebGL2 quirk: if ending with a render pass with a custom viewport, the viewport isn't
         // reset for the next render pass so add an empty render pass without a custom viewport
         #[cfg(all(feature = "webgl", target_arch = "wasm32", not(feature = "webgpu")))]
@@ -173,18 +620,86 @@ impl ViewNode for Mask2dPrepassNode {
 }
 
 #[derive(Component)]
-pub struct Mask2dPrepassTexture(CachedTexture);
+pub struct Mask2dPrepassTexture {
+    mask_texture: CachedTexture,
+    static_mask_texture: CachedTexture,
+    emission_texture: CachedTexture,
+    coverage_texture: CachedTexture,
+    /// Attachments rendered into instead when the view has a [`Mask2dMultisample`].
+    multisampled: Option<Mask2dMultisampledTextures>,
+    /// Whether the masks are written this frame, see [`Self::is_resolved`].
+    resolved: bool,
+}
+
+struct Mask2dMultisampledTextures {
+    mask_texture: CachedTexture,
+    static_mask_texture: CachedTexture,
+    emission_texture: CachedTexture,
+}
 
 impl Mask2dPrepassTexture {
-    const FORMAT: TextureFormat = TextureFormat::R16Uint;
+    pub const FORMAT: TextureFormat = TextureFormat::R16Uint;
+    pub const EMISSION_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+    pub const COVERAGE_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+    /// Color targets of the mask prepass for occluders in the static or dynamic layer.
+    pub fn color_targets(is_static: bool) -> Vec<Option<ColorTargetState>> {
+        // Only write into the mask layer the occluder belongs to
+        let (dynamic_writes, static_writes) = match is_static {
+            true => (ColorWrites::empty(), ColorWrites::ALL),
+            false => (ColorWrites::ALL, ColorWrites::empty()),
+        };
 
+        vec![
+            Some(ColorTargetState {
+                format: Self::FORMAT,
+                blend: None,
+                write_mask: dynamic_writes,
+            }),
+            Some(ColorTargetState {
+                format: Self::FORMAT,
+                blend: None,
+                write_mask: static_writes,
+            }),
+            Some(ColorTargetState {
+                format: Self::EMISSION_FORMAT,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            }),
+        ]
+    }
+
+    /// Mask of dynamic occluders.
     pub fn get(&self) -> &CachedTexture {
-        &self.0
+        &self.mask_texture
+    }
+
+    /// Mask of [`StaticMask2d`] occluders.
+    pub fn get_static(&self) -> &CachedTexture {
+        &self.static_mask_texture
+    }
+
+    /// Emission (rgb) and opacity (a) of all occluders, opacity includes their coverage.
+    pub fn get_emission(&self) -> &CachedTexture {
+        &self.emission_texture
+    }
+
+    /// Fraction of each pixel covered by any occluder, 1 everywhere without [`Mask2dMultisample`].
+    pub fn get_coverage(&self) -> &CachedTexture {
+        &self.coverage_texture
+    }
+
+    /// Whether occluders make it into the masks this frame. Multisampled masks are left empty
+    /// until the pipeline resolving them is compiled.
+    pub fn is_resolved(&self) -> bool {
+        self.resolved
     }
 }
 
 pub struct Mask2dItem {
     pub entity: Entity,
+    /// Mesh or image drawn by the item, sorted on so that items sharing it can be batched.
+    pub asset_id: UntypedAssetId,
     pub pipeline: CachedRenderPipelineId,
     pub draw_function: DrawFunctionId,
     pub batch_range: Range<u32>,
@@ -224,14 +739,17 @@ impl PhaseItem for Mask2dItem {
 }
 
 impl SortedPhaseItem for Mask2dItem {
-    type SortKey = ();
+    type SortKey = (CachedRenderPipelineId, UntypedAssetId);
 
     #[inline]
-    fn sort_key(&self) -> Self::SortKey {}
+    fn sort_key(&self) -> Self::SortKey {
+        (self.pipeline, self.asset_id)
+    }
 
     #[inline]
-    fn sort(_: &mut [Self]) {
-        // No sorting is needed as we are just masking things on the scene
+    fn sort(items: &mut [Self]) {
+        // Masking is order independent, group items that can be batched together instead
+        items.sort_unstable_by_key(|item| item.sort_key());
     }
 }
 
@@ -242,6 +760,132 @@ impl CachedRenderPipelinePhaseItem for Mask2dItem {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mask2dMeshPipelineKey {
+    pub mesh_key: Mesh2dPipelineKey,
+    /// Whether the mesh is rasterized into the static mask layer.
+    pub is_static: bool,
+}
+
+/// Material-agnostic pipeline drawing [`Mask2d`] meshes as plain occluders.
+#[derive(Resource)]
+pub struct Mask2dMeshPipeline {
+    mesh2d_pipeline: Mesh2dPipeline,
+    vertex_shader: Handle<Shader>,
+    fragment_shader: Handle<Shader>,
+}
+
+impl FromWorld for Mask2dMeshPipeline {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            mesh2d_pipeline: world.resource::<Mesh2dPipeline>().clone(),
+            vertex_shader: world.load_asset("shaders/mask2d_mesh.wgsl"),
+            fragment_shader: world.load_asset("shaders/mask2d.wgsl"),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for Mask2dMeshPipeline {
+    type Key = Mask2dMeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayoutRef,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh2d_pipeline.specialize(key.mesh_key, layout)?;
+
+        descriptor.label = Some("mask2d_mesh_pipeline".into());
+        // Overwrite shaders and fragment targets
+        descriptor.vertex.shader = self.vertex_shader.clone();
+        if let Some(fragment) = &mut descriptor.fragment {
+            fragment.shader = self.fragment_shader.clone();
+            fragment.targets = Mask2dPrepassTexture::color_targets(key.is_static);
+        }
+
+        // The sample count comes from the view's Mask2dMultisample through the mesh key
+        descriptor.depth_stencil = None;
+
+        Ok(descriptor)
+    }
+}
+
+impl GetBatchData for Mask2dMeshPipeline {
+    type Param = (
+        SRes<RenderMesh2dInstances>,
+        SRes<Mask2dMaterialMeshes>,
+        SRes<Mask2dObjects>,
+    );
+    /// Only meshes queued through their material are split by material.
+    type CompareData = (Option<Material2dBindGroupId>, AssetId<Mesh>);
+    type BufferData = Mesh2dUniform;
+
+    fn get_batch_data(
+        (mesh_instances, material_meshes, objects): &SystemParamItem<Self::Param>,
+        entity: Entity,
+    ) -> Option<(Self::BufferData, Option<Self::CompareData>)> {
+        let mesh_instance = mesh_instances.get(&entity)?;
+        let mut mesh_uniform = Mesh2dUniform::from(&mesh_instance.transforms);
+        // The mask vertex shader reads the object index from the otherwise unused flags
+        mesh_uniform.flags = objects.get(&entity)?;
+
+        Some((
+            mesh_uniform,
+            mesh_instance.automatic_batching.then_some((
+                material_meshes
+                    .contains(&entity)
+                    .then_some(mesh_instance.material_bind_group_id),
+                mesh_instance.mesh_asset_id,
+            )),
+        ))
+    }
+}
+
+pub struct Mask2dPrepassPipelineKey<M: Material2d> {
+    pub material_key: Material2dKey<M>,
+    /// Whether the mesh is rasterized into the static mask layer.
+    pub is_static: bool,
+    /// Quantized [`Mask2dAlphaCutoff`] for materials with [`Mask2dMaterial::mask_texture_alpha`].
+    pub alpha_cutoff: Option<u8>,
+}
+
+impl<M: Material2d> Eq for Mask2dPrepassPipelineKey<M> where M::Data: PartialEq {}
+
+impl<M: Material2d> PartialEq for Mask2dPrepassPipelineKey<M>
+where
+    M::Data: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.material_key == other.material_key
+            && self.is_static == other.is_static
+            && self.alpha_cutoff == other.alpha_cutoff
+    }
+}
+
+impl<M: Material2d> Clone for Mask2dPrepassPipelineKey<M>
+where
+    M::Data: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            material_key: self.material_key.clone(),
+            is_static: self.is_static,
+            alpha_cutoff: self.alpha_cutoff,
+        }
+    }
+}
+
+impl<M: Material2d> Hash for Mask2dPrepassPipelineKey<M>
+where
+    M::Data: Hash,
+{
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.material_key.hash(state);
+        self.is_static.hash(state);
+        self.alpha_cutoff.hash(state);
+    }
+}
+
 #[derive(Resource)]
 pub struct Mask2dPrepassPipeline<M: Material2d>(Material2dPipeline<M>);
 
@@ -249,40 +893,100 @@ impl<M: Material2d> SpecializedMeshPipeline for Mask2dPrepassPipeline<M>
 where
     M::Data: PartialEq + Eq + Hash + Clone,
 {
-    type Key = Material2dKey<M>;
+    type Key = Mask2dPrepassPipelineKey<M>;
 
     fn specialize(
         &self,
         key: Self::Key,
         layout: &MeshVertexBufferLayoutRef,
     ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
-        let mut descriptor = self.0.specialize(key, layout)?;
+        let mut descriptor = self.0.specialize(key.material_key, layout)?;
 
-        // Overwrite fragment target
+        // Overwrite fragment targets
         if let Some(fragment) = &mut descriptor.fragment {
-            fragment.targets = vec![Some(ColorTargetState {
-                format: Mask2dPrepassTexture::FORMAT,
-                blend: None,
-                write_mask: ColorWrites::ALL,
-            })];
+            if let Some(alpha_cutoff) = key.alpha_cutoff {
+                fragment.shader_defs.push(ShaderDefVal::UInt(
+                    "MASK2D_ALPHA_CUTOFF".into(),
+                    alpha_cutoff as u32,
+                ));
+            }
+
+            fragment.targets = Mask2dPrepassTexture::color_targets(key.is_static);
         }
 
-        descriptor.multisample = default();
+        // The sample count comes from the view's Mask2dMultisample through the mesh key
         descriptor.depth_stencil = None;
 
         Ok(descriptor)
     }
 }
 
-impl<M: Material2d> FromWorld for Mask2dPrepassPipeline<M> {
+impl<M: Mask2dMaterial> FromWorld for Mask2dPrepassPipeline<M> {
     fn from_world(world: &mut World) -> Self {
         let mut material2d_pipeline = Material2dPipeline::<M>::from_world(world);
-        // Overwrite fragment shader
-        material2d_pipeline.fragment_shader = Some(world.load_asset("shaders/mask2d.wgsl"));
+        // Overwrite shaders, the mask vertex shader passes the object index along
+        material2d_pipeline.vertex_shader = Some(world.load_asset("shaders/mask2d_mesh.wgsl"));
+        material2d_pipeline.fragment_shader = Some(match M::mask_fragment_shader() {
+            ShaderRef::Default => world.load_asset("shaders/mask2d.wgsl"),
+            ShaderRef::Handle(handle) => handle,
+            ShaderRef::Path(path) => world.load_asset(path),
+        });
         Self(material2d_pipeline)
     }
 }
 
+/// Fullscreen pipeline resolving multisampled masks into single sampled masks and coverage.
+#[derive(Resource)]
+pub struct Mask2dResolvePipeline {
+    layout: BindGroupLayout,
+    pipeline: CachedRenderPipelineId,
+}
+
+impl FromWorld for Mask2dResolvePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+
+        let layout = render_device.create_bind_group_layout(
+            "mask2d_resolve_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    // Multisampled mask texture
+                    texture_2d_multisampled(TextureSampleType::Uint),
+                    // Multisampled static mask texture
+                    texture_2d_multisampled(TextureSampleType::Uint),
+                ),
+            ),
+        );
+
+        let shader = world.load_asset("shaders/mask2d_resolve.wgsl");
+        let pipeline =
+            world
+                .resource::<PipelineCache>()
+                .queue_render_pipeline(RenderPipelineDescriptor {
+                    label: Some("mask2d_resolve_pipeline".into()),
+                    layout: vec![layout.clone()],
+                    vertex: fullscreen_shader_vertex_state(),
+                    fragment: Some(FragmentState {
+                        shader,
+                        shader_defs: vec![],
+                        entry_point: "fragment".into(),
+                        targets: vec![
+                            Some(Mask2dPrepassTexture::FORMAT.into()),
+                            Some(Mask2dPrepassTexture::FORMAT.into()),
+                            Some(Mask2dPrepassTexture::COVERAGE_FORMAT.into()),
+                        ],
+                    }),
+                    push_constant_ranges: vec![],
+                    primitive: default(),
+                    depth_stencil: None,
+                    multisample: default(),
+                });
+
+        Self { layout, pipeline }
+    }
+}
+
 pub fn extract_core_2d_camera_phases(
     mut commands: Commands,
     mut mask_2d_phases: ResMut<ViewSortedRenderPhases<Mask2dItem>>,
@@ -306,6 +1010,13 @@ pub fn extract_core_2d_camera_phases(
     mask_2d_phases.retain(|camera_entity, _| live_entities.contains(camera_entity));
 }
 
+type DrawMask2dMesh = (
+    SetItemPipeline,
+    SetMesh2dViewBindGroup<0>,
+    SetMesh2dBindGroup<1>,
+    DrawMesh2d,
+);
+
 type DrawMaterial2d<M> = (
     SetItemPipeline,
     SetMesh2dViewBindGroup<0>,
@@ -315,52 +1026,170 @@ type DrawMaterial2d<M> = (
 );
 
 #[allow(clippy::too_many_arguments)]
-pub fn queue_mask2d_meshes<M: Material2d>(
-    mut q_views: Query<(Entity, &ExtractedView, &VisibleEntities)>,
-    q_mask2d: Query<(), With<Mask2d>>,
+pub fn queue_mask2d_meshes<M: Mask2dMaterial>(
+    mut q_views: Query<(Entity, &ExtractedView, Option<&Mask2dMultisample>)>,
+    mask_visible_entities: Res<Mask2dVisibleEntities>,
+    q_mask2d: Query<(Has<StaticMask2d>, Option<&Mask2dAlphaCutoff>), With<Mask2d>>,
     mask_draw_functions: Res<DrawFunctions<Mask2dItem>>,
     mask2d_pipeline: Res<Mask2dPrepassPipeline<M>>,
     mut pipelines: ResMut<SpecializedMeshPipelines<Mask2dPrepassPipeline<M>>>,
     pipeline_cache: Res<PipelineCache>,
-    msaa: Res<Msaa>,
     render_meshes: Res<RenderAssets<GpuMesh>>,
     render_materials: Res<RenderAssets<PreparedMaterial2d<M>>>,
     mut render_mesh_instances: ResMut<RenderMesh2dInstances>,
     render_material_instances: Res<RenderMaterial2dInstances<M>>,
+    mask_only_material_instances: Res<Mask2dMaterialInstances<M>>,
     mut mask_render_phases: ResMut<ViewSortedRenderPhases<Mask2dItem>>,
+    mut static_mask_dirty: ResMut<StaticMask2dDirty>,
+    mut material_meshes: ResMut<Mask2dMaterialMeshes>,
+    objects: Res<Mask2dObjects>,
 ) where
     M::Data: PartialEq + Eq + Hash + Clone,
 {
-    if render_material_instances.is_empty() {
+    let custom_shader = !matches!(M::mask_fragment_shader(), ShaderRef::Default);
+    // Everything else is drawn by the occluder pipeline
+    if (render_material_instances.is_empty() && mask_only_material_instances.is_empty())
+        || !(custom_shader || M::mask_texture_alpha())
+    {
         return;
     }
 
-    for (view_entity, view, visible_entities) in &mut q_views {
+    for (view_entity, view, multisample) in &mut q_views {
         let Some(mask_phase) = mask_render_phases.get_mut(&view_entity) else {
             continue;
         };
 
         let draw_transparent_2d = mask_draw_functions.read().id::<DrawMaterial2d<M>>();
 
-        let view_key = Mesh2dPipelineKey::from_msaa_samples(msaa.samples())
-            | Mesh2dPipelineKey::from_hdr(view.hdr);
+        let view_key =
+            Mesh2dPipelineKey::from_msaa_samples(Mask2dMultisample::samples(multisample))
+                | Mesh2dPipelineKey::from_hdr(view.hdr);
+
+        let Some(visible_entities) = mask_visible_entities.get(&view_entity) else {
+            continue;
+        };
 
-        for visible_entity in visible_entities.iter::<WithMesh2d>() {
+        for visible_entity in visible_entities {
             // Only mask entities that contains the Mask2d component
-            if !q_mask2d.contains(*visible_entity) {
+            let Ok((is_static, alpha_cutoff)) = q_mask2d.get(*visible_entity) else {
                 continue;
-            }
+            };
 
-            let Some(material_asset_id) = render_material_instances.get(visible_entity) else {
+            let Some(material_asset_id) = render_material_instances
+                .get(visible_entity)
+                .or_else(|| mask_only_material_instances.get(visible_entity))
+            else {
                 continue;
             };
+            if (!custom_shader && alpha_cutoff.is_none()) || objects.get(visible_entity).is_none() {
+                continue;
+            }
+            material_meshes.insert(*visible_entity);
+
             let Some(mesh_instance) = render_mesh_instances.get_mut(visible_entity) else {
                 continue;
             };
-            let Some(material_2d) = render_materials.get(*material_asset_id) else {
+            let (Some(material_2d), Some(mesh)) = (
+                render_materials.get(*material_asset_id),
+                render_meshes.get(mesh_instance.mesh_asset_id),
+            ) else {
+                // Static occluders that are still loading must be flooded once they are ready
+                static_mask_dirty.0 |= is_static;
+                continue;
+            };
+            let mesh_key =
+                view_key | Mesh2dPipelineKey::from_primitive_topology(mesh.primitive_topology());
+
+            let pipeline_id = pipelines.specialize(
+                &pipeline_cache,
+                &mask2d_pipeline,
+                Mask2dPrepassPipelineKey {
+                    material_key: Material2dKey {
+                        mesh_key,
+                        bind_group_data: material_2d.key.clone(),
+                    },
+                    is_static,
+                    alpha_cutoff: alpha_cutoff
+                        .filter(|_| M::mask_texture_alpha())
+                        .map(Mask2dAlphaCutoff::quantize),
+                },
+                &mesh.layout,
+            );
+
+            let pipeline_id = match pipeline_id {
+                Ok(id) => id,
+                Err(err) => {
+                    error!("{}", err);
+                    continue;
+                }
+            };
+
+            // Same goes for static occluders with pipelines that are still compiling
+            static_mask_dirty.0 |=
+                is_static && pipeline_cache.get_render_pipeline(pipeline_id).is_none();
+
+            mesh_instance.material_bind_group_id = material_2d.get_bind_group_id();
+
+            mask_phase.add(Mask2dItem {
+                entity: *visible_entity,
+                asset_id: mesh_instance.mesh_asset_id.untyped(),
+                draw_function: draw_transparent_2d,
+                pipeline: pipeline_id,
+                // Batching is done in batch_and_prepare_render_phase
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::NONE,
+            });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn queue_mask2d_occluders(
+    mut q_views: Query<(Entity, &ExtractedView, Option<&Mask2dMultisample>)>,
+    mask_visible_entities: Res<Mask2dVisibleEntities>,
+    q_mask2d: Query<Has<StaticMask2d>, With<Mask2d>>,
+    mask_draw_functions: Res<DrawFunctions<Mask2dItem>>,
+    mask2d_pipeline: Res<Mask2dMeshPipeline>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<Mask2dMeshPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    render_meshes: Res<RenderAssets<GpuMesh>>,
+    render_mesh_instances: Res<RenderMesh2dInstances>,
+    material_meshes: Res<Mask2dMaterialMeshes>,
+    objects: Res<Mask2dObjects>,
+    mut mask_render_phases: ResMut<ViewSortedRenderPhases<Mask2dItem>>,
+    mut static_mask_dirty: ResMut<StaticMask2dDirty>,
+) {
+    for (view_entity, view, multisample) in &mut q_views {
+        let Some(mask_phase) = mask_render_phases.get_mut(&view_entity) else {
+            continue;
+        };
+
+        let draw_mask2d_mesh = mask_draw_functions.read().id::<DrawMask2dMesh>();
+
+        let view_key =
+            Mesh2dPipelineKey::from_msaa_samples(Mask2dMultisample::samples(multisample))
+                | Mesh2dPipelineKey::from_hdr(view.hdr);
+
+        let Some(visible_entities) = mask_visible_entities.get(&view_entity) else {
+            continue;
+        };
+
+        for visible_entity in visible_entities {
+            // Only mask entities that contains the Mask2d component
+            let Ok(is_static) = q_mask2d.get(*visible_entity) else {
+                continue;
+            };
+            // Already queued through its material or beyond the object limit
+            if material_meshes.contains(visible_entity) || objects.get(visible_entity).is_none() {
+                continue;
+            }
+
+            let Some(mesh_instance) = render_mesh_instances.get(visible_entity) else {
                 continue;
             };
             let Some(mesh) = render_meshes.get(mesh_instance.mesh_asset_id) else {
+                // Static occluders that are still loading must be flooded once they are ready
+                static_mask_dirty.0 |= is_static;
                 continue;
             };
             let mesh_key =
@@ -369,9 +1198,9 @@ pub fn queue_mask2d_meshes<M: Material2d>(
             let pipeline_id = pipelines.specialize(
                 &pipeline_cache,
                 &mask2d_pipeline,
-                Material2dKey {
+                Mask2dMeshPipelineKey {
                     mesh_key,
-                    bind_group_data: material_2d.key.clone(),
+                    is_static,
                 },
                 &mesh.layout,
             );
@@ -384,11 +1213,14 @@ pub fn queue_mask2d_meshes<M: Material2d>(
                 }
             };
 
-            mesh_instance.material_bind_group_id = material_2d.get_bind_group_id();
+            // Same goes for static occluders with pipelines that are still compiling
+            static_mask_dirty.0 |=
+                is_static && pipeline_cache.get_render_pipeline(pipeline_id).is_none();
 
             mask_phase.add(Mask2dItem {
                 entity: *visible_entity,
-                draw_function: draw_transparent_2d,
+                asset_id: mesh_instance.mesh_asset_id.untyped(),
+                draw_function: draw_mask2d_mesh,
                 pipeline: pipeline_id,
                 // Batching is done in batch_and_prepare_render_phase
                 batch_range: 0..1,
@@ -398,32 +1230,389 @@ pub fn queue_mask2d_meshes<M: Material2d>(
     }
 }
 
-fn prepare_mask2d_texture(
+type Mask2dObjectQuery = (
+    Option<&'static Mask2dProperties>,
+    Option<&'static LightAnimation2d>,
+);
+
+fn extract_mask2d_objects(
+    mut objects: ResMut<Mask2dObjects>,
+    mask_visible_entities: Extract<Res<Mask2dVisibleEntities>>,
+    q_mask2d: Extract<Query<Mask2dObjectQuery, With<Mask2d>>>,
+) {
+    let objects = objects.as_mut();
+    objects.indices.clear();
+    objects.properties.clear();
+
+    // Index 0 is reserved for empty space
+    objects
+        .properties
+        .push(GpuMask2dProperties::from(&Mask2dProperties::default()));
+
+    for entity in mask_visible_entities.unique() {
+        let Ok((properties, animation)) = q_mask2d.get(entity) else {
+            continue;
+        };
+
+        if objects.properties.len() > Mask2dObjects::MAX_OBJECTS {
+            warn_once!(
+                "More than {} visible Mask2d entities, the rest are left out of the mask.",
+                Mask2dObjects::MAX_OBJECTS
+            );
+            break;
+        }
+
+        let mut properties = GpuMask2dProperties::from(&properties.copied().unwrap_or_default());
+        if let Some(animation) = animation {
+            properties.emission *= animation.factor().to_vec3();
+        }
+
+        let index = objects.properties.push(properties);
+        objects.indices.insert(entity, index as u32);
+    }
+}
+
+type MaskOnlyMesh2dQuery = (
+    &'static ViewVisibility,
+    &'static GlobalTransform,
+    &'static Mesh2dHandle,
+    Has<NoAutomaticBatching>,
+);
+
+/// Bevy only extracts meshes seen by a camera's main pass, which leaves out meshes on mask layers
+/// only.
+fn extract_mask_only_mesh2d(
     mut commands: Commands,
-    q_views: Query<(Entity, &ViewTarget), With<Mask2dPrepass>>,
+    mut render_mesh_instances: ResMut<RenderMesh2dInstances>,
+    mask_visible_entities: Extract<Res<Mask2dVisibleEntities>>,
+    q_meshes: Extract<Query<MaskOnlyMesh2dQuery>>,
+) {
+    let mut entities = Vec::new();
+
+    for entity in mask_visible_entities.unique() {
+        let Ok((view_visibility, transform, handle, no_automatic_batching)) = q_meshes.get(entity)
+        else {
+            continue;
+        };
+        // Already extracted along with the main pass
+        if view_visibility.get() {
+            continue;
+        }
+
+        entities.push((entity, Mesh2d));
+        render_mesh_instances.insert(
+            entity,
+            RenderMesh2dInstance {
+                transforms: Mesh2dTransforms {
+                    world_from_local: (&transform.affine()).into(),
+                    flags: MeshFlags::empty().bits(),
+                },
+                mesh_asset_id: handle.0.id(),
+                material_bind_group_id: Material2dBindGroupId::default(),
+                automatic_batching: !no_automatic_batching,
+            },
+        );
+    }
+
+    commands.insert_or_spawn_batch(entities);
+}
+
+fn extract_mask_only_materials<M: Material2d>(
+    mut material_instances: ResMut<Mask2dMaterialInstances<M>>,
+    mask_visible_entities: Extract<Res<Mask2dVisibleEntities>>,
+    q_materials: Extract<Query<(&ViewVisibility, &Handle<M>)>>,
+) {
+    material_instances.clear();
+
+    for entity in mask_visible_entities.unique() {
+        let Ok((view_visibility, handle)) = q_materials.get(entity) else {
+            continue;
+        };
+        // Already extracted into `RenderMaterial2dInstances`
+        if !view_visibility.get() {
+            material_instances.insert(entity, handle.id());
+        }
+    }
+}
+
+fn prepare_mask2d_objects(
+    mut objects: ResMut<Mask2dObjects>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    objects
+        .properties
+        .write_buffer(&render_device, &render_queue);
+}
+
+/// Sample counts above 1 supported by the adapter for every multisampled mask prepass attachment.
+#[derive(Resource)]
+struct Mask2dSampleCounts(Vec<u32>);
+
+impl FromWorld for Mask2dSampleCounts {
+    fn from_world(world: &mut World) -> Self {
+        let adapter = world.resource::<RenderAdapter>();
+        let mask_flags = adapter
+            .get_texture_format_features(Mask2dPrepassTexture::FORMAT)
+            .flags;
+        // Resolving Rgba16Float is guaranteed wherever it can be multisampled
+        let emission_flags = adapter
+            .get_texture_format_features(Mask2dPrepassTexture::EMISSION_FORMAT)
+            .flags;
+
+        Self(
+            [2, 4, 8, 16]
+                .into_iter()
+                .filter(|&count| {
+                    mask_flags.sample_count_supported(count)
+                        && emission_flags.sample_count_supported(count)
+                })
+                .collect(),
+        )
+    }
+}
+
+fn extract_mask2d_multisample(
+    mut commands: Commands,
+    sample_counts: Res<Mask2dSampleCounts>,
+    q_cameras: Extract<Query<(Entity, &Mask2dMultisample)>>,
+) {
+    for (entity, multisample) in q_cameras.iter() {
+        let samples = sample_counts
+            .0
+            .iter()
+            .copied()
+            .filter(|&count| count <= multisample.0)
+            .max()
+            .unwrap_or(1);
+
+        if samples != multisample.0.max(1) {
+            warn_once!(
+                "Mask2dMultisample({}) is not supported by the adapter, using {} samples instead.",
+                multisample.0,
+                samples
+            );
+        }
+
+        commands
+            .get_or_spawn(entity)
+            .insert(Mask2dMultisample(samples));
+    }
+}
+
+fn clear_mask2d_material_meshes(mut material_meshes: ResMut<Mask2dMaterialMeshes>) {
+    material_meshes.clear();
+}
+
+pub(crate) fn prepare_mask2d_texture(
+    mut commands: Commands,
+    q_views: Query<(Entity, &ViewTarget, Option<&Mask2dMultisample>), With<Mask2dPrepass>>,
     mut texture_cache: ResMut<TextureCache>,
     render_device: Res<RenderDevice>,
+    resolve_pipeline: Res<Mask2dResolvePipeline>,
+    pipeline_cache: Res<PipelineCache>,
 ) {
-    for (entity, view) in q_views.iter() {
+    let resolve_ready = pipeline_cache
+        .get_render_pipeline(resolve_pipeline.pipeline)
+        .is_some();
+
+    for (entity, view, multisample) in q_views.iter() {
         let mut size = view.main_texture().size();
         size.depth_or_array_layers = 1;
 
-        let texture = texture_cache.get(
-            &render_device,
-            TextureDescriptor {
-                label: Some("mask2d_prepass_texture"),
+        let mask_texture_desc =
+            |name: &'static str, format: TextureFormat, sample_count: u32| TextureDescriptor {
+                label: Some(name),
                 size,
                 mip_level_count: 1,
-                sample_count: 1,
+                sample_count,
                 dimension: TextureDimension::D2,
-                format: Mask2dPrepassTexture::FORMAT,
+                format,
                 usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
                 view_formats: &[],
-            },
+            };
+
+        let mask_texture = texture_cache.get(
+            &render_device,
+            mask_texture_desc("mask2d_prepass_texture", Mask2dPrepassTexture::FORMAT, 1),
+        );
+        let static_mask_texture = texture_cache.get(
+            &render_device,
+            mask_texture_desc(
+                "static_mask2d_prepass_texture",
+                Mask2dPrepassTexture::FORMAT,
+                1,
+            ),
+        );
+        let emission_texture = texture_cache.get(
+            &render_device,
+            mask_texture_desc(
+                "emission2d_prepass_texture",
+                Mask2dPrepassTexture::EMISSION_FORMAT,
+                1,
+            ),
+        );
+        let coverage_texture = texture_cache.get(
+            &render_device,
+            mask_texture_desc(
+                "coverage2d_prepass_texture",
+                Mask2dPrepassTexture::COVERAGE_FORMAT,
+                1,
+            ),
         );
 
-        commands
-            .entity(entity)
-            .insert(Mask2dPrepassTexture(texture));
+        let samples = Mask2dMultisample::samples(multisample);
+        let multisampled = (samples > 1).then(|| Mask2dMultisampledTextures {
+            mask_texture: texture_cache.get(
+                &render_device,
+                mask_texture_desc(
+                    "mask2d_prepass_multisampled_texture",
+                    Mask2dPrepassTexture::FORMAT,
+                    samples,
+                ),
+            ),
+            static_mask_texture: texture_cache.get(
+                &render_device,
+                mask_texture_desc(
+                    "static_mask2d_prepass_multisampled_texture",
+                    Mask2dPrepassTexture::FORMAT,
+                    samples,
+                ),
+            ),
+            emission_texture: texture_cache.get(
+                &render_device,
+                mask_texture_desc(
+                    "emission2d_prepass_multisampled_texture",
+                    Mask2dPrepassTexture::EMISSION_FORMAT,
+                    samples,
+                ),
+            ),
+        });
+
+        commands.entity(entity).insert(Mask2dPrepassTexture {
+            mask_texture,
+            static_mask_texture,
+            emission_texture,
+            coverage_texture,
+            resolved: multisampled.is_none() || resolve_ready,
+            multisampled,
+        });
     }
 }
+
+type Mask2dCameraQuery = (
+    Entity,
+    &'static Camera,
+    &'static Frustum,
+    Option<&'static Mask2dLayers>,
+    Option<&'static RenderLayers>,
+);
+
+type Mask2dVisibilityQuery = (
+    Entity,
+    &'static InheritedVisibility,
+    &'static GlobalTransform,
+    Option<&'static Aabb>,
+    Has<NoFrustumCulling>,
+    Option<&'static Mask2dVisibility>,
+    Option<&'static Mask2dLayers>,
+    Option<&'static RenderLayers>,
+);
+
+/// Culls [`Mask2d`] entities against the frustum and mask layers of each camera.
+///
+/// Occluders on mask layers only are missing from the camera's [`VisibleEntities`], so the
+/// culling is done separately from the main pass.
+fn check_mask2d_visibility(
+    mut visible_entities: ResMut<Mask2dVisibleEntities>,
+    q_cameras: Query<Mask2dCameraQuery, With<Mask2dPrepass>>,
+    q_mask2d: Query<Mask2dVisibilityQuery, With<Mask2d>>,
+) {
+    visible_entities.0.clear();
+
+    for (camera_entity, camera, frustum, camera_mask_layers, camera_layers) in q_cameras.iter() {
+        if !camera.is_active {
+            continue;
+        }
+
+        let camera_layers = Mask2dLayers::resolve(camera_mask_layers, camera_layers);
+
+        let entities = visible_entities.0.entry(camera_entity).or_default();
+        for (
+            entity,
+            inherited_visibility,
+            transform,
+            aabb,
+            no_frustum_culling,
+            visibility,
+            mask_layers,
+            layers,
+        ) in q_mask2d.iter()
+        {
+            if !inherited_visibility.get() || visibility == Some(&Mask2dVisibility::MainOnly) {
+                continue;
+            }
+
+            if !camera_layers.intersects(Mask2dLayers::resolve(mask_layers, layers)) {
+                continue;
+            }
+
+            // Same frustum test as the main pass
+            if let (Some(aabb), false) = (aabb, no_frustum_culling) {
+                if !frustum.intersects_obb(aabb, &transform.affine(), true, false) {
+                    continue;
+                }
+            }
+
+            entities.push(entity);
+        }
+    }
+}
+
+/// Removes [`Mask2dVisibility::MaskOnly`] entities from the main pass of every camera.
+fn hide_mask_only_mask2d(
+    mut q_cameras: Query<&mut VisibleEntities>,
+    q_mask2d: Query<&Mask2dVisibility, With<Mask2d>>,
+) {
+    let is_shown = |entity: &Entity| q_mask2d.get(*entity) != Ok(&Mask2dVisibility::MaskOnly);
+
+    for mut visible_entities in q_cameras.iter_mut() {
+        visible_entities.get_mut::<WithMesh2d>().retain(is_shown);
+        visible_entities.get_mut::<WithSprite>().retain(is_shown);
+    }
+}
+
+type ChangedStaticMask2dFilter = (
+    With<StaticMask2d>,
+    Or<(
+        Added<StaticMask2d>,
+        Changed<GlobalTransform>,
+        Changed<InheritedVisibility>,
+        Changed<Mesh2dHandle>,
+        Changed<Sprite>,
+        Changed<TextureAtlas>,
+        Changed<Handle<Image>>,
+        Changed<Mask2dAlphaCutoff>,
+        Changed<Mask2dVisibility>,
+        Changed<Mask2dLayers>,
+        Changed<RenderLayers>,
+    )>,
+);
+
+type ChangedMask2dCameraFilter = (
+    With<Mask2dPrepass>,
+    Or<(
+        Changed<Mask2dLayers>,
+        Changed<RenderLayers>,
+        Changed<Mask2dMultisample>,
+    )>,
+);
+
+fn detect_static_mask2d_changes(
+    mut dirty: ResMut<StaticMask2dDirty>,
+    q_changed: Query<(), ChangedStaticMask2dFilter>,
+    q_changed_cameras: Query<(), ChangedMask2dCameraFilter>,
+    mut removed: RemovedComponents<StaticMask2d>,
+) {
+    dirty.0 = !q_changed.is_empty() || !q_changed_cameras.is_empty() || removed.read().count() > 0;
+}
@@ -0,0 +1,492 @@
+use bevy::{
+    ecs::{
+        entity::EntityHashSet,
+        query::ROQueryItem,
+        system::{lifetimeless::SRes, SystemParamItem},
+    },
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand,
+            RenderCommandResult, SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases,
+        },
+        render_resource::{
+            binding_types::{sampler, storage_buffer_read_only, texture_2d},
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, BufferUsages,
+            BufferVec, FragmentState, MultisampleState, PipelineCache, RenderPipelineDescriptor,
+            SamplerBindingType, ShaderStages, ShaderType, SpecializedRenderPipeline,
+            SpecializedRenderPipelines, TextureSampleType, VertexState,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        texture::GpuImage,
+        view::ExtractedView,
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+    sprite::{
+        ExtractedSprite, ExtractedSprites, Mesh2dPipeline, SetMesh2dViewBindGroup, SpriteSystem,
+    },
+    utils::HashMap,
+};
+
+use crate::mask2d::{
+    Mask2d, Mask2dAlphaCutoff, Mask2dItem, Mask2dMultisample, Mask2dObjects, Mask2dPrepassPlugin,
+    Mask2dPrepassTexture, Mask2dVisibleEntities, StaticMask2d, StaticMask2dDirty,
+};
+
+/// Makes [`Sprite`] entities with a [`Mask2d`] component occlude,
+/// including sprites that uses a [`TextureAtlas`].
+///
+/// Texels with an alpha below [`Mask2dAlphaCutoff`] are left out of the mask.
+/// [`Mask2dPrepassPlugin`] is added along if needed.
+pub struct Mask2dSpritePlugin;
+
+impl Plugin for Mask2dSpritePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<Mask2dPrepassPlugin>() {
+            app.add_plugins(Mask2dPrepassPlugin);
+        }
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<SpriteMask2dMeta>()
+            .init_resource::<SpecializedRenderPipelines<SpriteMask2dPipeline>>()
+            .add_render_command::<Mask2dItem, DrawSpriteMask2d>()
+            .add_systems(
+                ExtractSchedule,
+                extract_mask_only_sprites.after(SpriteSystem::ExtractSprites),
+            )
+            .add_systems(
+                Render,
+                (
+                    queue_sprite_mask2ds.in_set(RenderSet::Queue),
+                    prepare_sprite_mask2d_bind_groups.in_set(RenderSet::PrepareBindGroups),
+                ),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.init_resource::<SpriteMask2dPipeline>();
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct SpriteMask2dPipeline {
+    view_layout: BindGroupLayout,
+    instance_layout: BindGroupLayout,
+    texture_layout: BindGroupLayout,
+    shader: Handle<Shader>,
+}
+
+impl FromWorld for SpriteMask2dPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let mesh2d_pipeline = world.resource::<Mesh2dPipeline>();
+
+        let instance_layout = render_device.create_bind_group_layout(
+            "sprite_mask2d_instance_bind_group_layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::VERTEX_FRAGMENT,
+                // Sprite instances
+                storage_buffer_read_only::<SpriteMask2dInstance>(false),
+            ),
+        );
+
+        let texture_layout = render_device.create_bind_group_layout(
+            "sprite_mask2d_texture_bind_group_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    // Sprite texture
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // Sprite sampler
+                    sampler(SamplerBindingType::Filtering),
+                ),
+            ),
+        );
+
+        Self {
+            view_layout: mesh2d_pipeline.view_layout.clone(),
+            instance_layout,
+            texture_layout,
+            shader: world.load_asset("shaders/mask2d_sprite.wgsl"),
+        }
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct SpriteMask2dPipelineKey {
+    /// Whether the sprite is rasterized into the static mask layer.
+    pub is_static: bool,
+    /// Sample count of the view's [`Mask2dMultisample`].
+    pub samples: u32,
+}
+
+impl SpecializedRenderPipeline for SpriteMask2dPipeline {
+    type Key = SpriteMask2dPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("sprite_mask2d_pipeline".into()),
+            layout: vec![
+                self.view_layout.clone(),
+                self.instance_layout.clone(),
+                self.texture_layout.clone(),
+            ],
+            vertex: VertexState {
+                shader: self.shader.clone(),
+                shader_defs: vec![],
+                entry_point: "vertex".into(),
+                buffers: vec![],
+            },
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: Mask2dPrepassTexture::color_targets(key.is_static),
+            }),
+            push_constant_ranges: vec![],
+            primitive: default(),
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: key.samples,
+                ..default()
+            },
+        }
+    }
+}
+
+#[derive(ShaderType, Debug, Clone, Copy)]
+struct SpriteMask2dInstance {
+    world_from_local: Mat4,
+    uv_offset_scale: Vec4,
+    alpha_cutoff: f32,
+    object_index: u32,
+}
+
+#[derive(Resource)]
+pub struct SpriteMask2dMeta {
+    instances: BufferVec<SpriteMask2dInstance>,
+    /// Image used by each sprite entity queued this frame.
+    images: HashMap<Entity, AssetId<Image>>,
+}
+
+impl Default for SpriteMask2dMeta {
+    fn default() -> Self {
+        let mut instances = BufferVec::new(BufferUsages::STORAGE);
+        instances.set_label(Some("sprite_mask2d_instances"));
+
+        Self {
+            instances,
+            images: HashMap::default(),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct SpriteMask2dBindGroups {
+    instance_bind_group: BindGroup,
+    texture_bind_groups: HashMap<AssetId<Image>, BindGroup>,
+}
+
+type MaskOnlySpriteQuery = (
+    &'static ViewVisibility,
+    &'static Sprite,
+    &'static GlobalTransform,
+    &'static Handle<Image>,
+    Option<&'static TextureAtlas>,
+);
+
+/// Bevy only extracts sprites seen by a camera's main pass, which leaves out sprites on mask
+/// layers only. Sliced sprites are masked whole.
+fn extract_mask_only_sprites(
+    mut extracted_sprites: ResMut<ExtractedSprites>,
+    texture_atlases: Extract<Res<Assets<TextureAtlasLayout>>>,
+    mask_visible_entities: Extract<Res<Mask2dVisibleEntities>>,
+    q_sprites: Extract<Query<MaskOnlySpriteQuery>>,
+) {
+    for entity in mask_visible_entities.unique() {
+        let Ok((view_visibility, sprite, transform, handle, atlas)) = q_sprites.get(entity) else {
+            continue;
+        };
+        // Already extracted along with the main pass
+        if view_visibility.get() {
+            continue;
+        }
+
+        let atlas_rect = atlas.and_then(|atlas| atlas.texture_rect(&texture_atlases));
+        let rect = match (atlas_rect, sprite.rect) {
+            (None, None) => None,
+            (None, Some(sprite_rect)) => Some(sprite_rect),
+            (Some(atlas_rect), None) => Some(atlas_rect.as_rect()),
+            (Some(atlas_rect), Some(mut sprite_rect)) => {
+                sprite_rect.min += atlas_rect.min.as_vec2();
+                sprite_rect.max += atlas_rect.min.as_vec2();
+                Some(sprite_rect)
+            }
+        };
+
+        extracted_sprites.sprites.insert(
+            entity,
+            ExtractedSprite {
+                color: sprite.color.into(),
+                transform: *transform,
+                rect,
+                custom_size: sprite.custom_size,
+                flip_x: sprite.flip_x,
+                flip_y: sprite.flip_y,
+                image_handle_id: handle.id(),
+                anchor: sprite.anchor.as_vec(),
+                original_entity: None,
+            },
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_sprite_mask2ds(
+    q_views: Query<(Entity, Option<&Mask2dMultisample>), With<ExtractedView>>,
+    mask_visible_entities: Res<Mask2dVisibleEntities>,
+    q_mask2d: Query<(Has<StaticMask2d>, Option<&Mask2dAlphaCutoff>), With<Mask2d>>,
+    mask_draw_functions: Res<DrawFunctions<Mask2dItem>>,
+    sprite_mask2d_pipeline: Res<SpriteMask2dPipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<SpriteMask2dPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    extracted_sprites: Res<ExtractedSprites>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    mut sprite_mask2d_meta: ResMut<SpriteMask2dMeta>,
+    mut mask_render_phases: ResMut<ViewSortedRenderPhases<Mask2dItem>>,
+    mut static_mask_dirty: ResMut<StaticMask2dDirty>,
+    objects: Res<Mask2dObjects>,
+    mut view_entities: Local<EntityHashSet>,
+) {
+    let sprite_mask2d_meta = sprite_mask2d_meta.as_mut();
+    sprite_mask2d_meta.instances.clear();
+    sprite_mask2d_meta.images.clear();
+
+    let draw_sprite_mask2d = mask_draw_functions.read().id::<DrawSpriteMask2d>();
+
+    for (view_entity, multisample) in q_views.iter() {
+        let Some(mask_phase) = mask_render_phases.get_mut(&view_entity) else {
+            continue;
+        };
+
+        let Some(visible_entities) = mask_visible_entities.get(&view_entity) else {
+            continue;
+        };
+
+        view_entities.clear();
+        view_entities.extend(visible_entities.iter().copied());
+
+        for (entity, extracted_sprite) in extracted_sprites.sprites.iter() {
+            // Sliced sprites are spawned as separate entities
+            let original_entity = extracted_sprite.original_entity.unwrap_or(*entity);
+            if !view_entities.contains(&original_entity) {
+                continue;
+            }
+
+            // Only mask sprites that contains the Mask2d component
+            let Ok((is_static, alpha_cutoff)) = q_mask2d.get(original_entity) else {
+                continue;
+            };
+            let Some(object_index) = objects.get(&original_entity) else {
+                continue;
+            };
+
+            let Some(gpu_image) = gpu_images.get(extracted_sprite.image_handle_id) else {
+                // Static occluders that are still loading must be flooded once they are ready
+                static_mask_dirty.0 |= is_static;
+                continue;
+            };
+
+            let pipeline_id = pipelines.specialize(
+                &pipeline_cache,
+                &sprite_mask2d_pipeline,
+                SpriteMask2dPipelineKey {
+                    is_static,
+                    samples: Mask2dMultisample::samples(multisample),
+                },
+            );
+
+            // Same goes for static occluders with pipelines that are still compiling
+            static_mask_dirty.0 |=
+                is_static && pipeline_cache.get_render_pipeline(pipeline_id).is_none();
+
+            // Same quad calculation as Bevy's sprite pipeline
+            let image_size = gpu_image.size.as_vec2();
+            let mut quad_size = image_size;
+
+            let mut uv_offset_scale = match extracted_sprite.rect {
+                Some(rect) => {
+                    let rect_size = rect.size();
+                    quad_size = rect_size;
+                    Vec4::new(
+                        rect.min.x / image_size.x,
+                        rect.max.y / image_size.y,
+                        rect_size.x / image_size.x,
+                        -rect_size.y / image_size.y,
+                    )
+                }
+                None => Vec4::new(0.0, 1.0, 1.0, -1.0),
+            };
+
+            if extracted_sprite.flip_x {
+                uv_offset_scale.x += uv_offset_scale.z;
+                uv_offset_scale.z *= -1.0;
+            }
+            if extracted_sprite.flip_y {
+                uv_offset_scale.y += uv_offset_scale.w;
+                uv_offset_scale.w *= -1.0;
+            }
+
+            if let Some(custom_size) = extracted_sprite.custom_size {
+                quad_size = custom_size;
+            }
+
+            let world_from_local = extracted_sprite.transform.compute_matrix()
+                * Mat4::from_scale_rotation_translation(
+                    quad_size.extend(1.0),
+                    Quat::IDENTITY,
+                    (quad_size * (-extracted_sprite.anchor - Vec2::splat(0.5))).extend(0.0),
+                );
+
+            let index = sprite_mask2d_meta.instances.push(SpriteMask2dInstance {
+                world_from_local,
+                uv_offset_scale,
+                alpha_cutoff: alpha_cutoff.copied().unwrap_or_default().0,
+                object_index,
+            }) as u32;
+            sprite_mask2d_meta
+                .images
+                .insert(*entity, extracted_sprite.image_handle_id);
+
+            mask_phase.add(Mask2dItem {
+                entity: *entity,
+                asset_id: extracted_sprite.image_handle_id.untyped(),
+                draw_function: draw_sprite_mask2d,
+                pipeline: pipeline_id,
+                // Each sprite draws its own instance
+                batch_range: index..index + 1,
+                extra_index: PhaseItemExtraIndex::NONE,
+            });
+        }
+    }
+}
+
+fn prepare_sprite_mask2d_bind_groups(
+    mut commands: Commands,
+    mut sprite_mask2d_meta: ResMut<SpriteMask2dMeta>,
+    sprite_mask2d_pipeline: Res<SpriteMask2dPipeline>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if sprite_mask2d_meta.instances.is_empty() {
+        commands.remove_resource::<SpriteMask2dBindGroups>();
+        return;
+    }
+
+    sprite_mask2d_meta
+        .instances
+        .write_buffer(&render_device, &render_queue);
+
+    let Some(instance_binding) = sprite_mask2d_meta.instances.binding() else {
+        return;
+    };
+
+    let instance_bind_group = render_device.create_bind_group(
+        "sprite_mask2d_instance_bind_group",
+        &sprite_mask2d_pipeline.instance_layout,
+        &BindGroupEntries::single(instance_binding),
+    );
+
+    let mut texture_bind_groups = HashMap::default();
+    for image_id in sprite_mask2d_meta.images.values() {
+        if texture_bind_groups.contains_key(image_id) {
+            continue;
+        }
+
+        let Some(gpu_image) = gpu_images.get(*image_id) else {
+            continue;
+        };
+
+        texture_bind_groups.insert(
+            *image_id,
+            render_device.create_bind_group(
+                "sprite_mask2d_texture_bind_group",
+                &sprite_mask2d_pipeline.texture_layout,
+                &BindGroupEntries::sequential((&gpu_image.texture_view, &gpu_image.sampler)),
+            ),
+        );
+    }
+
+    commands.insert_resource(SpriteMask2dBindGroups {
+        instance_bind_group,
+        texture_bind_groups,
+    });
+}
+
+type DrawSpriteMask2d = (
+    SetItemPipeline,
+    SetMesh2dViewBindGroup<0>,
+    SetSpriteMask2dBindGroups<1>,
+    DrawSpriteMask2dInstance,
+);
+
+/// Sets the instance bind group at `I` and the texture bind group at `I + 1`.
+pub struct SetSpriteMask2dBindGroups<const I: usize>;
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetSpriteMask2dBindGroups<I> {
+    type Param = (SRes<SpriteMask2dBindGroups>, SRes<SpriteMask2dMeta>);
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    #[inline]
+    fn render<'w>(
+        item: &P,
+        _view: ROQueryItem<'w, Self::ViewQuery>,
+        _entity: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        (bind_groups, sprite_mask2d_meta): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let bind_groups = bind_groups.into_inner();
+
+        let Some(texture_bind_group) = sprite_mask2d_meta
+            .images
+            .get(&item.entity())
+            .and_then(|image_id| bind_groups.texture_bind_groups.get(image_id))
+        else {
+            return RenderCommandResult::Failure;
+        };
+
+        pass.set_bind_group(I, &bind_groups.instance_bind_group, &[]);
+        pass.set_bind_group(I + 1, texture_bind_group, &[]);
+
+        RenderCommandResult::Success
+    }
+}
+
+pub struct DrawSpriteMask2dInstance;
+impl<P: PhaseItem> RenderCommand<P> for DrawSpriteMask2dInstance {
+    type Param = ();
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    #[inline]
+    fn render<'w>(
+        item: &P,
+        _view: ROQueryItem<'w, Self::ViewQuery>,
+        _entity: Option<ROQueryItem<'w, Self::ItemQuery>>,
+        _param: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        // Quad made out of 2 triangles
+        pass.draw(0..6, item.batch_range().clone());
+
+        RenderCommandResult::Success
+    }
+}
@@ -1,27 +1,35 @@
 use bevy::{
+    core::FrameCount,
     core_pipeline::{
         core_2d::graph::{Core2d, Node2d},
         fullscreen_vertex_shader::fullscreen_shader_vertex_state,
     },
+    ecs::entity::{EntityHashMap, EntityHashSet},
     ecs::query::QueryItem,
     prelude::*,
     render::{
+        camera::{Exposure, ExtractedCamera},
         extract_component::{ExtractComponent, ExtractComponentPlugin},
+        render_asset::RenderAssets,
         render_graph::{
             NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
         },
         render_resource::{
-            binding_types::{sampler, texture_2d, texture_storage_2d, uniform_buffer},
+            binding_types::{
+                sampler, storage_buffer_read_only, texture_2d, texture_storage_2d, uniform_buffer,
+            },
             BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries,
             CachedComputePipelineId, CachedRenderPipelineId, ColorTargetState, ColorWrites,
-            ComputePassDescriptor, ComputePipelineDescriptor, DynamicUniformBuffer, FilterMode,
-            FragmentState, PipelineCache, RenderPassColorAttachment, RenderPassDescriptor,
-            RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages,
-            ShaderType, StorageTextureAccess, TextureDescriptor, TextureDimension, TextureFormat,
-            TextureSampleType, TextureUsages, TextureViewDescriptor,
+            ComputePassDescriptor, ComputePipelineDescriptor, DynamicUniformBuffer, Extent3d,
+            FilterMode, FragmentState, PipelineCache, RenderPassColorAttachment,
+            RenderPassDescriptor, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+            SamplerDescriptor, ShaderStages, ShaderType, SpecializedRenderPipeline,
+            SpecializedRenderPipelines, StorageTextureAccess, TextureDescriptor, TextureDimension,
+            TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+            UniformBuffer,
         },
         renderer::{RenderContext, RenderDevice, RenderQueue},
-        texture::{CachedTexture, TextureCache},
+        texture::{CachedTexture, FallbackImage, GpuImage, ImageLoaderSettings, TextureCache},
         view::ViewTarget,
         Render, RenderApp, RenderSet,
     },
@@ -37,11 +45,20 @@ impl Plugin for RadianceCascadesPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(ExtractComponentPlugin::<RadianceCascadesConfig>::default());
 
+        if !app.is_plugin_added::<crate::light2d::Light2dPlugin>() {
+            app.add_plugins(crate::light2d::Light2dPlugin);
+        }
+        if !app.is_plugin_added::<crate::fog2d::Fog2dPlugin>() {
+            app.add_plugins(crate::fog2d::Fog2dPlugin);
+        }
+
         let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
             return;
         };
 
         render_app
+            .init_resource::<SpecializedRenderPipelines<RadianceCascadesPipeline>>()
+            .init_resource::<RadianceCascadesHistory>()
             .add_render_graph_node::<ViewNodeRunner<RadianceCascadesNode>>(
                 Core2d,
                 RadianceCascadesLabel,
@@ -58,6 +75,7 @@ impl Plugin for RadianceCascadesPlugin {
             .add_systems(
                 Render,
                 (
+                    prepare_radiance_cascades_apply_pipelines.in_set(RenderSet::Prepare),
                     calculate_cascade_count.in_set(RenderSet::PrepareResources),
                     (
                         prepare_radiance_cascades_textures,
@@ -89,13 +107,23 @@ impl ViewNode for RadianceCascadesNode {
         &'static RadianceCascadesTextures,
         &'static RadianceCascadesCount,
         &'static RadianceCascadesBuffer,
+        &'static RadianceCascadesApplyPipelineId,
+        &'static crate::mask2d::Mask2dPrepassTexture,
     );
 
     fn run<'w>(
         &self,
-        _graph: &mut RenderGraphContext,
+        graph: &mut RenderGraphContext,
         render_context: &mut RenderContext<'w>,
-        (view, bind_groups, textures, cascade_count, buffer): QueryItem<'w, Self::ViewQuery>,
+        (
+            view,
+            bind_groups,
+            textures,
+            cascade_count,
+            buffer,
+            apply_pipeline_id,
+            mask_texture,
+        ): QueryItem<'w, Self::ViewQuery>,
         world: &'w World,
     ) -> Result<(), NodeRunError> {
         let pipeline = world.resource::<RadianceCascadesPipeline>();
@@ -107,13 +135,15 @@ impl ViewNode for RadianceCascadesNode {
             Some(radiance_cascades_no_merge_pipeline),
             Some(radiance_cascades_merge_pipeline),
             Some(radiance_cascades_mipmap_pipeline),
+            Some(radiance_cascades_mipmap_blit_pipeline),
             Some(radiance_cascades_apply_pipeline),
         ) = (
             pipeline_cache.get_compute_pipeline(pipeline.dist_field_pipeline),
             pipeline_cache.get_compute_pipeline(pipeline.radiance_cascades_no_merge_pipeline),
             pipeline_cache.get_compute_pipeline(pipeline.radiance_cascades_merge_pipeline),
             pipeline_cache.get_compute_pipeline(pipeline.radiance_cascades_mipmap_pipeline),
-            pipeline_cache.get_render_pipeline(pipeline.radiance_cascades_apply_pipeline),
+            pipeline_cache.get_render_pipeline(pipeline.radiance_cascades_mipmap_blit_pipeline),
+            pipeline_cache.get_render_pipeline(apply_pipeline_id.0),
         )
         else {
             return Ok(());
@@ -229,6 +259,49 @@ impl ViewNode for RadianceCascadesNode {
             );
         }
 
+        // Keep the irradiance around for the bounce light of the next frame
+        render_context.command_encoder().copy_texture_to_texture(
+            textures.radiance_mipmap_texture.texture.as_image_copy(),
+            textures.radiance_history_texture.texture.as_image_copy(),
+            textures.radiance_history_texture.texture.size(),
+        );
+
+        // Radiance mip chain, each level downsampled from the previous one
+        for (level, blit_bind_group) in bind_groups
+            .radiance_mipmap_blit_bind_groups
+            .iter()
+            .enumerate()
+        {
+            let mut radiance_cascades_mipmap_blit_render_pass = render_context
+                .command_encoder()
+                .begin_render_pass(&RenderPassDescriptor {
+                    label: Some("radiance_cascades_mipmap_blit_render_pass"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: &textures.radiance_mipmap_levels[level + 1],
+                        resolve_target: None,
+                        ops: default(),
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+
+            radiance_cascades_mipmap_blit_render_pass
+                .set_pipeline(radiance_cascades_mipmap_blit_pipeline);
+            radiance_cascades_mipmap_blit_render_pass.set_bind_group(0, blit_bind_group, &[]);
+            radiance_cascades_mipmap_blit_render_pass.draw(0..3, 0..1);
+        }
+
+        let (Some(lights), Some(gi), Some(mask_properties)) = (
+            world
+                .resource::<crate::light2d::Light2dBuffers>()
+                .binding(&graph.view_entity()),
+            buffer.gi_buffer.binding(),
+            world.resource::<crate::mask2d::Mask2dObjects>().binding(),
+        ) else {
+            return Ok(());
+        };
+
         let post_process = view.post_process_write();
         {
             // Radiance cascades apply
@@ -241,6 +314,14 @@ impl ViewNode for RadianceCascadesNode {
                         &pipeline.main_sampler,
                         &textures.radiance_mipmap_texture.default_view,
                         &pipeline.radiance_sampler,
+                        &textures.dist_field_texture.default_view,
+                        lights,
+                        gi,
+                        &mask_texture.get().default_view,
+                        &mask_texture.get_static().default_view,
+                        mask_properties,
+                        &mask_texture.get_emission().default_view,
+                        &textures.radiance_direction_texture.default_view,
                     )),
                 );
 
@@ -278,14 +359,21 @@ struct RadianceCascadesPipeline {
     dist_field_bind_group_layout: BindGroupLayout,
     radiance_cascades_bind_group_layout: BindGroupLayout,
     radiance_cascades_mipmap_bind_group_layout: BindGroupLayout,
+    radiance_cascades_mipmap_blit_bind_group_layout: BindGroupLayout,
     radiance_cascades_apply_bind_group_layout: BindGroupLayout,
     dist_field_pipeline: CachedComputePipelineId,
     radiance_cascades_no_merge_pipeline: CachedComputePipelineId,
     radiance_cascades_merge_pipeline: CachedComputePipelineId,
     radiance_cascades_mipmap_pipeline: CachedComputePipelineId,
-    radiance_cascades_apply_pipeline: CachedRenderPipelineId,
+    radiance_cascades_mipmap_blit_pipeline: CachedRenderPipelineId,
+    /// Specialized per view target format, see [`RadianceCascadesApplyPipelineKey`].
+    radiance_cascades_apply_shader: Handle<Shader>,
     main_sampler: Sampler,
     radiance_sampler: Sampler,
+    fog_sampler: Sampler,
+    dist_field_sampler: Sampler,
+    /// Tileable blue noise jittering rays, two independent channels.
+    blue_noise: Handle<Image>,
 }
 
 impl FromWorld for RadianceCascadesPipeline {
@@ -298,9 +386,17 @@ impl FromWorld for RadianceCascadesPipeline {
         let radiance_cascades_shader = world.load_asset("shaders/radiance_cascades.wgsl");
         let radiance_cascades_mipmap_shader =
             world.load_asset("shaders/radiance_cascades_mipmap.wgsl");
+        let radiance_cascades_mipmap_blit_shader =
+            world.load_asset("shaders/radiance_cascades_mipmap_blit.wgsl");
         let radiance_cascades_apply_shader =
             world.load_asset("shaders/radiance_cascades_apply.wgsl");
 
+        // Texture
+        let blue_noise = world.resource::<AssetServer>().load_with_settings(
+            "textures/blue_noise.png",
+            |settings: &mut ImageLoaderSettings| settings.is_srgb = false,
+        );
+
         // Bind group layout
         let dist_field_bind_group_layout = render_device.create_bind_group_layout(
             "dist_field_bind_group_layout",
@@ -308,7 +404,11 @@ impl FromWorld for RadianceCascadesPipeline {
                 ShaderStages::COMPUTE,
                 (
                     // Jfa texture
-                    texture_2d(TextureSampleType::Uint),
+                    texture_2d(TextureSampleType::Sint),
+                    // Static jfa cache texture
+                    texture_2d(TextureSampleType::Sint),
+                    // Mask coverage texture
+                    texture_2d(TextureSampleType::Float { filterable: false }),
                     // Distance field texture
                     texture_storage_2d(
                         RadianceCascadesTextures::DIST_FIELD_FORMAT,
@@ -327,8 +427,26 @@ impl FromWorld for RadianceCascadesPipeline {
                     uniform_buffer::<Probe>(true),
                     // Main texture
                     texture_2d(TextureSampleType::Float { filterable: false }),
-                    // Distance field texture
+                    // Emission texture
                     texture_2d(TextureSampleType::Float { filterable: false }),
+                    // Mask texture
+                    texture_2d(TextureSampleType::Uint),
+                    // Static mask texture
+                    texture_2d(TextureSampleType::Uint),
+                    // Mask properties
+                    storage_buffer_read_only::<crate::mask2d::GpuMask2dProperties>(false),
+                    // Lights
+                    storage_buffer_read_only::<crate::light2d::GpuLight2d>(false),
+                    // Fog
+                    uniform_buffer::<crate::fog2d::GpuFog2d>(false),
+                    // Fog density texture
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // Fog density sampler
+                    sampler(SamplerBindingType::Filtering),
+                    // Distance field texture
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // Distance field sampler
+                    sampler(SamplerBindingType::Filtering),
                     // Cascade n+1 texture
                     texture_2d(TextureSampleType::Float { filterable: false }),
                     // Cascade n texture
@@ -336,6 +454,12 @@ impl FromWorld for RadianceCascadesPipeline {
                         RadianceCascadesTextures::CASCADE_FORMAT,
                         StorageTextureAccess::WriteOnly,
                     ),
+                    // Blue noise texture
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // Radiance history texture
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // Radiance history sampler
+                    sampler(SamplerBindingType::Filtering),
                 ),
             ),
         );
@@ -354,10 +478,31 @@ impl FromWorld for RadianceCascadesPipeline {
                         RadianceCascadesTextures::CASCADE_FORMAT,
                         StorageTextureAccess::WriteOnly,
                     ),
+                    // Direction texture
+                    texture_storage_2d(
+                        RadianceCascadesTextures::CASCADE_FORMAT,
+                        StorageTextureAccess::WriteOnly,
+                    ),
+                    // Blue noise texture
+                    texture_2d(TextureSampleType::Float { filterable: false }),
                 ),
             ),
         );
 
+        let radiance_cascades_mipmap_blit_bind_group_layout = render_device
+            .create_bind_group_layout(
+                "radiance_cascades_mipmap_blit_bind_group_layout",
+                &BindGroupLayoutEntries::sequential(
+                    ShaderStages::FRAGMENT,
+                    (
+                        // Previous mip level
+                        texture_2d(TextureSampleType::Float { filterable: true }),
+                        // Mipmap sampler
+                        sampler(SamplerBindingType::Filtering),
+                    ),
+                ),
+            );
+
         let radiance_cascades_apply_bind_group_layout = render_device.create_bind_group_layout(
             "radiance_cascades_apply_bind_group_layout",
             &BindGroupLayoutEntries::sequential(
@@ -371,6 +516,22 @@ impl FromWorld for RadianceCascadesPipeline {
                     texture_2d(TextureSampleType::Float { filterable: true }),
                     // Mipmap sampler
                     sampler(SamplerBindingType::Filtering),
+                    // Distance field texture
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // Lights
+                    storage_buffer_read_only::<crate::light2d::GpuLight2d>(false),
+                    // GI settings
+                    uniform_buffer::<GiSettings>(false),
+                    // Mask texture
+                    texture_2d(TextureSampleType::Uint),
+                    // Static mask texture
+                    texture_2d(TextureSampleType::Uint),
+                    // Mask properties
+                    storage_buffer_read_only::<crate::mask2d::GpuMask2dProperties>(false),
+                    // Emission texture
+                    texture_2d(TextureSampleType::Float { filterable: false }),
+                    // Direction texture
+                    texture_2d(TextureSampleType::Float { filterable: true }),
                 ),
             ),
         );
@@ -416,13 +577,13 @@ impl FromWorld for RadianceCascadesPipeline {
                 push_constant_ranges: vec![],
             });
 
-        let radiance_cascades_apply_pipeline =
+        let radiance_cascades_mipmap_blit_pipeline =
             pipeline_cache.queue_render_pipeline(RenderPipelineDescriptor {
-                label: Some("radiance_cascades_apply_pipeline".into()),
-                layout: vec![radiance_cascades_apply_bind_group_layout.clone()],
+                label: Some("radiance_cascades_mipmap_blit_pipeline".into()),
+                layout: vec![radiance_cascades_mipmap_blit_bind_group_layout.clone()],
                 vertex: fullscreen_shader_vertex_state(),
                 fragment: Some(FragmentState {
-                    shader: radiance_cascades_apply_shader,
+                    shader: radiance_cascades_mipmap_blit_shader,
                     shader_defs: vec![],
                     entry_point: "fragment".into(),
                     targets: vec![Some(ColorTargetState {
@@ -441,23 +602,87 @@ impl FromWorld for RadianceCascadesPipeline {
             dist_field_bind_group_layout,
             radiance_cascades_bind_group_layout,
             radiance_cascades_mipmap_bind_group_layout,
+            radiance_cascades_mipmap_blit_bind_group_layout,
             radiance_cascades_apply_bind_group_layout,
             dist_field_pipeline,
             radiance_cascades_no_merge_pipeline,
             radiance_cascades_merge_pipeline,
             radiance_cascades_mipmap_pipeline,
-            radiance_cascades_apply_pipeline,
+            radiance_cascades_mipmap_blit_pipeline,
+            radiance_cascades_apply_shader,
             main_sampler: render_device.create_sampler(&SamplerDescriptor::default()),
             radiance_sampler: render_device.create_sampler(&SamplerDescriptor {
                 label: Some("radiance_sampler"),
                 mipmap_filter: FilterMode::Linear,
                 mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
                 ..default()
             }),
+            fog_sampler: render_device.create_sampler(&SamplerDescriptor {
+                label: Some("fog_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..default()
+            }),
+            dist_field_sampler: render_device.create_sampler(&SamplerDescriptor {
+                label: Some("dist_field_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..default()
+            }),
+            blue_noise,
         }
     }
 }
 
+impl SpecializedRenderPipeline for RadianceCascadesPipeline {
+    type Key = RadianceCascadesApplyPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        RenderPipelineDescriptor {
+            label: Some("radiance_cascades_apply_pipeline".into()),
+            layout: vec![self.radiance_cascades_apply_bind_group_layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: self.radiance_cascades_apply_shader.clone(),
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: key.texture_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            push_constant_ranges: vec![],
+            primitive: default(),
+            depth_stencil: None,
+            multisample: default(),
+        }
+    }
+}
+
+fn prepare_radiance_cascades_apply_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<RadianceCascadesPipeline>>,
+    pipeline: Res<RadianceCascadesPipeline>,
+    q_views: Query<(Entity, &ViewTarget), With<RadianceCascadesConfig>>,
+) {
+    for (entity, view) in q_views.iter() {
+        let pipeline_id = pipelines.specialize(
+            &pipeline_cache,
+            &pipeline,
+            RadianceCascadesApplyPipelineKey {
+                texture_format: view.main_texture_format(),
+            },
+        );
+
+        commands
+            .entity(entity)
+            .insert(RadianceCascadesApplyPipelineId(pipeline_id));
+    }
+}
+
 fn calculate_cascade_count(
     mut commands: Commands,
     q_views: Query<(Entity, &ViewTarget, &RadianceCascadesConfig)>,
@@ -512,8 +737,12 @@ fn prepare_radiance_cascades_textures(
         &RadianceCascadesConfig,
     )>,
     mut texture_cache: ResMut<TextureCache>,
+    mut history: ResMut<RadianceCascadesHistory>,
     render_device: Res<RenderDevice>,
+    mut live_entities: Local<EntityHashSet>,
 ) {
+    live_entities.clear();
+
     for (entity, view, cascade_count, cascade_config) in q_views.iter() {
         let mut size = view.main_texture().size();
         size.depth_or_array_layers = 1;
@@ -557,37 +786,111 @@ fn prepare_radiance_cascades_textures(
             cascade_texture_desc("radiance_cascade_1_texture"),
         );
 
+        let mipmap_texture_desc = |name: &'static str, mip_level_count: u32| TextureDescriptor {
+            label: Some(name),
+            size: half_size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: RadianceCascadesTextures::CASCADE_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        };
+
+        // Full chain down to a single texel
+        let mip_level_count = u32::max(half_size.width, half_size.height).ilog2() + 1;
         let radiance_mipmap_texture = texture_cache.get(
             &render_device,
-            TextureDescriptor {
-                label: Some("radiance_mipmap_texture"),
-                size: half_size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: TextureDimension::D2,
-                format: RadianceCascadesTextures::CASCADE_FORMAT,
-                usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
-                view_formats: &[],
-            },
+            mipmap_texture_desc("radiance_mipmap_texture", mip_level_count),
+        );
+        let radiance_mipmap_levels = (0..mip_level_count)
+            .map(|level| {
+                radiance_mipmap_texture
+                    .texture
+                    .create_view(&TextureViewDescriptor {
+                        label: Some("radiance_mipmap_level_view"),
+                        base_mip_level: level,
+                        mip_level_count: Some(1),
+                        ..default()
+                    })
+            })
+            .collect();
+        let radiance_direction_texture = texture_cache.get(
+            &render_device,
+            mipmap_texture_desc("radiance_direction_texture", 1),
         );
 
+        // Kept across frames, unlike the texture cache, as long as the view has the same size
+        live_entities.insert(entity);
+        let radiance_history_texture = history
+            .0
+            .entry(entity)
+            .and_modify(|texture| {
+                if texture.texture.size() != half_size {
+                    *texture = create_history_texture(&render_device, half_size);
+                }
+            })
+            .or_insert_with(|| create_history_texture(&render_device, half_size))
+            .clone();
+
         commands.entity(entity).insert(RadianceCascadesTextures {
             dist_field_texture,
             radiance_cascades_texture0,
             radiance_cascades_texture1,
             radiance_mipmap_texture,
+            radiance_mipmap_levels,
+            radiance_direction_texture,
+            radiance_history_texture,
             is_texture0: cascade_count.0 % 2 != 0,
         });
     }
+
+    // Clear out all dead views.
+    history
+        .0
+        .retain(|view_entity, _| live_entities.contains(view_entity));
+}
+
+fn create_history_texture(render_device: &RenderDevice, size: Extent3d) -> CachedTexture {
+    let texture = render_device.create_texture(&TextureDescriptor {
+        label: Some("radiance_history_texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: RadianceCascadesTextures::CASCADE_FORMAT,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let default_view = texture.create_view(&TextureViewDescriptor::default());
+
+    CachedTexture {
+        texture,
+        default_view,
+    }
 }
 
 fn prepare_radiance_cascades_buffers(
     mut commands: Commands,
-    q_configs: Query<(Entity, &RadianceCascadesConfig, &RadianceCascadesCount)>,
+    q_configs: Query<(
+        Entity,
+        &ExtractedCamera,
+        &RadianceCascadesConfig,
+        &RadianceCascadesCount,
+    )>,
+    frame_count: Res<FrameCount>,
+    images: Res<RenderAssets<GpuImage>>,
+    pipeline: Res<RadianceCascadesPipeline>,
     render_device: Res<RenderDevice>,
     render_queue: Res<RenderQueue>,
 ) {
-    for (entity, config, cascade_count) in q_configs.iter() {
+    // The fallback image would jitter every probe identically
+    let blue_noise_loaded = images.get(&pipeline.blue_noise).is_some();
+
+    for (entity, camera, config, cascade_count) in q_configs.iter() {
         let mut probe_buffers = DynamicUniformBuffer::default();
         probe_buffers.set_label(Some("radiance_cascades_probe_buffers"));
 
@@ -595,13 +898,10 @@ fn prepare_radiance_cascades_buffers(
         let mut probe_buffer_offsets = Vec::with_capacity(cascade_count);
 
         for c in 0..cascade_count {
-            let width = 1 << (c as u32 + config.resolution_factor);
-            let start = config.interval0 * (1.0 - f32::powi(4.0, c as i32)) / -3.0;
-            let range = config.interval0 * f32::powi(4.0, c as i32);
             let probe = Probe {
-                width,
-                start,
-                range,
+                jitter: (config.ray_jitter && blue_noise_loaded) as u32,
+                frame_index: frame_count.0,
+                ..Probe::cascade(config, c)
             };
 
             let offset = probe_buffers.push(&probe);
@@ -610,31 +910,71 @@ fn prepare_radiance_cascades_buffers(
 
         probe_buffers.write_buffer(&render_device, &render_queue);
 
+        // Exposure relative to Bevy's default, so that GI is unchanged unless exposure is set
+        let exposure = camera.exposure / Exposure::default().exposure();
+        let mut gi_buffer = UniformBuffer::from(GiSettings {
+            tint: config.gi_tint.to_linear().to_vec3() * config.gi_intensity * exposure,
+            ambient: config.ambient.to_linear().to_vec3() * exposure,
+            surface_depth: config.surface_depth,
+            rim_light: config.rim_light,
+            cosine: config.cosine_irradiance as u32,
+            upsampling: config.gi_upsampling as u32,
+        });
+        gi_buffer.set_label(Some("radiance_cascades_gi_buffer"));
+        gi_buffer.write_buffer(&render_device, &render_queue);
+
         commands.entity(entity).insert(RadianceCascadesBuffer {
             probe_buffers,
             probe_buffer_offsets,
+            gi_buffer,
         });
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn prepare_radiance_cascades_bind_groups(
     mut commands: Commands,
     q_views: Query<(
         Entity,
         &ViewTarget,
+        &crate::mask2d::Mask2dPrepassTexture,
         &crate::jfa::JfaPrepassTextures,
         &RadianceCascadesTextures,
         &RadianceCascadesBuffer,
+        &crate::fog2d::Fog2dBuffer,
     )>,
+    jfa_static_caches: Res<crate::jfa::JfaStaticCaches>,
+    mask2d_objects: Res<crate::mask2d::Mask2dObjects>,
+    light_buffers: Res<crate::light2d::Light2dBuffers>,
+    images: Res<RenderAssets<GpuImage>>,
+    fallback_image: Res<FallbackImage>,
     render_device: Res<RenderDevice>,
     pipeline: Res<RadianceCascadesPipeline>,
 ) {
-    for (entity, view, jfa_textures, textures, buffer) in q_views.iter() {
+    let Some(mask_properties) = mask2d_objects.binding() else {
+        return;
+    };
+    // Bound but unused until the blue noise is loaded
+    let blue_noise = images
+        .get(&pipeline.blue_noise)
+        .unwrap_or(&fallback_image.d2);
+
+    for (entity, view, mask_texture, jfa_textures, textures, buffer, fog) in q_views.iter() {
+        let (Some(jfa_static_cache), Some(lights), Some(fog_uniform)) = (
+            jfa_static_caches.get(&entity),
+            light_buffers.binding(&entity),
+            fog.uniform.binding(),
+        ) else {
+            continue;
+        };
+
         let dist_field_bind_group = render_device.create_bind_group(
             "dist_field_bind_group",
             &pipeline.dist_field_bind_group_layout,
             &BindGroupEntries::sequential((
                 &jfa_textures.main_texture().default_view,
+                &jfa_static_cache.default_view,
+                &mask_texture.get_coverage().default_view,
                 &textures.dist_field_texture.default_view,
             )),
         );
@@ -645,9 +985,21 @@ fn prepare_radiance_cascades_bind_groups(
             &BindGroupEntries::sequential((
                 &buffer.probe_buffers,
                 view.main_texture_view(),
+                &mask_texture.get_emission().default_view,
+                &mask_texture.get().default_view,
+                &mask_texture.get_static().default_view,
+                mask_properties.clone(),
+                lights.clone(),
+                fog_uniform.clone(),
+                &fog.density_texture,
+                &pipeline.fog_sampler,
                 &textures.dist_field_texture.default_view,
+                &pipeline.dist_field_sampler,
                 &textures.radiance_cascades_texture0.default_view,
                 &textures.radiance_cascades_texture1.default_view,
+                &blue_noise.texture_view,
+                &textures.radiance_history_texture.default_view,
+                &pipeline.radiance_sampler,
             )),
         );
 
@@ -657,9 +1009,21 @@ fn prepare_radiance_cascades_bind_groups(
             &BindGroupEntries::sequential((
                 &buffer.probe_buffers,
                 view.main_texture_view(),
+                &mask_texture.get_emission().default_view,
+                &mask_texture.get().default_view,
+                &mask_texture.get_static().default_view,
+                mask_properties.clone(),
+                lights.clone(),
+                fog_uniform.clone(),
+                &fog.density_texture,
+                &pipeline.fog_sampler,
                 &textures.dist_field_texture.default_view,
+                &pipeline.dist_field_sampler,
                 &textures.radiance_cascades_texture1.default_view,
                 &textures.radiance_cascades_texture0.default_view,
+                &blue_noise.texture_view,
+                &textures.radiance_history_texture.default_view,
+                &pipeline.radiance_sampler,
             )),
         );
 
@@ -669,15 +1033,31 @@ fn prepare_radiance_cascades_bind_groups(
             &BindGroupEntries::sequential((
                 &buffer.probe_buffers,
                 &textures.main_texture().default_view,
-                &textures.radiance_mipmap_texture.default_view,
+                &textures.radiance_mipmap_levels[0],
+                &textures.radiance_direction_texture.default_view,
+                &blue_noise.texture_view,
             )),
         );
 
+        let radiance_mipmap_blit_bind_groups = textures
+            .radiance_mipmap_levels
+            .iter()
+            .take(textures.radiance_mipmap_levels.len() - 1)
+            .map(|previous_level| {
+                render_device.create_bind_group(
+                    "radiance_cascades_mipmap_blit_bind_group",
+                    &pipeline.radiance_cascades_mipmap_blit_bind_group_layout,
+                    &BindGroupEntries::sequential((previous_level, &pipeline.radiance_sampler)),
+                )
+            })
+            .collect();
+
         commands.entity(entity).insert(RadianceCascadesBindGroups {
             dist_field_bind_group,
             radiance_cascades_01_bind_group,
             radiance_cascades_10_bind_group,
             radiance_cascades_mipmap_bind_group,
+            radiance_mipmap_blit_bind_groups,
         });
     }
 }
@@ -693,6 +1073,33 @@ pub struct RadianceCascadesConfig {
     resolution_factor: u32,
     /// Interval length of cascade 0 in pixel unit.
     interval0: f32,
+    /// How main pass colors hit by rays are turned into light.
+    emission_decoding: EmissionDecoding,
+    /// Whether main pass colors below the negative threshold emit negative light (darkness).
+    negative_light: bool,
+    /// Multiplier of the GI added onto the main pass.
+    gi_intensity: f32,
+    /// Color multiplied with the GI.
+    gi_tint: Color,
+    /// Minimum GI every pixel receives.
+    ambient: Color,
+    /// How deep into occluders, in pixel unit, the apply pass looks for their surface to light
+    /// them from outside. Occluders receive the GI of the probes inside them when 0, the default.
+    surface_depth: f32,
+    /// Extra light on the edges of occluders, relative to the light they receive.
+    rim_light: f32,
+    /// Whether rays take texel sized steps near occluders so that thin walls always block light.
+    conservative_march: bool,
+    /// Whether rays sample the distance field bilinearly and refine their hits onto the surface.
+    filtered_dist_field: bool,
+    /// Whether ray angles and interval starts are jittered every frame with blue noise.
+    ray_jitter: bool,
+    /// Seed of the jitter noise, the same seed and frame count always jitter identically.
+    jitter_seed: u32,
+    /// Whether occluder surfaces weight incoming light by the cosine to their normal.
+    cosine_irradiance: bool,
+    /// How the probe grid is upsampled to every pixel by the apply pass.
+    gi_upsampling: GiUpsampling,
 }
 
 impl RadianceCascadesConfig {
@@ -704,6 +1111,7 @@ impl RadianceCascadesConfig {
         Self {
             resolution_factor,
             interval0,
+            ..default()
         }
     }
 
@@ -721,6 +1129,101 @@ impl RadianceCascadesConfig {
         self
     }
 
+    /// New config with emission decoding.
+    pub fn with_emission_decoding(mut self, emission_decoding: EmissionDecoding) -> Self {
+        self.emission_decoding = emission_decoding;
+        self
+    }
+
+    /// New config with negative light enabled or disabled.
+    pub fn with_negative_light(mut self, negative_light: bool) -> Self {
+        self.negative_light = negative_light;
+        self
+    }
+
+    /// New config with GI intensity (clamped above 0).
+    pub fn with_gi_intensity(mut self, gi_intensity: f32) -> Self {
+        self.gi_intensity = f32::max(gi_intensity, 0.0);
+        self
+    }
+
+    /// New config with GI tint.
+    pub fn with_gi_tint(mut self, gi_tint: Color) -> Self {
+        self.gi_tint = gi_tint;
+        self
+    }
+
+    /// New config with ambient floor.
+    pub fn with_ambient(mut self, ambient: Color) -> Self {
+        self.ambient = ambient;
+        self
+    }
+
+    /// New config with occluder surface search depth in pixel unit (clamped above 0).
+    pub fn with_surface_depth(mut self, surface_depth: f32) -> Self {
+        self.surface_depth = f32::max(surface_depth, 0.0);
+        self
+    }
+
+    /// New config with occluder rim light (clamped above 0).
+    pub fn with_rim_light(mut self, rim_light: f32) -> Self {
+        self.rim_light = f32::max(rim_light, 0.0);
+        self
+    }
+
+    /// New config with conservative marching enabled or disabled.
+    ///
+    /// Rays then step no further than the distance field guarantees to be empty and cross
+    /// occluders one texel at a time, so that one pixel walls and grazing hits cannot be skipped.
+    /// Costs more steps per ray near geometry, the step budget grows with the interval length and
+    /// rays running out of steps right next to an occluder are treated as blocked.
+    pub fn with_conservative_march(mut self, conservative_march: bool) -> Self {
+        self.conservative_march = conservative_march;
+        self
+    }
+
+    /// New config with bilinear distance field sampling enabled or disabled.
+    ///
+    /// Rays then march with sub-pixel precision and stop on the edge of occluders rather than on
+    /// texel centers, smoothing stair-stepped shadow edges.
+    pub fn with_filtered_dist_field(mut self, filtered_dist_field: bool) -> Self {
+        self.filtered_dist_field = filtered_dist_field;
+        self
+    }
+
+    /// New config with ray jitter enabled or disabled.
+    ///
+    /// Rays of each probe are rotated and their interval starts shifted by a blue noise that
+    /// changes every frame, trading the banding of low angular resolutions for noise. The mipmap
+    /// pass then blurs neighbouring probes to hide most of it. Rays are not jittered until the
+    /// blue noise is loaded.
+    pub fn with_ray_jitter(mut self, ray_jitter: bool) -> Self {
+        self.ray_jitter = ray_jitter;
+        self
+    }
+
+    /// New config with ray jitter seed.
+    pub fn with_jitter_seed(mut self, jitter_seed: u32) -> Self {
+        self.jitter_seed = jitter_seed;
+        self
+    }
+
+    /// New config with cosine weighted irradiance enabled or disabled.
+    ///
+    /// Surfaces lit by the apply pass from outside (see [`Self::with_surface_depth`]) then
+    /// receive more light from the directions they face. A uniform environment lights them the
+    /// same either way.
+    pub fn with_cosine_irradiance(mut self, cosine_irradiance: bool) -> Self {
+        self.cosine_irradiance = cosine_irradiance;
+        self
+    }
+
+    /// New config with GI upsampling filter.
+    pub fn with_gi_upsampling(mut self, gi_upsampling: GiUpsampling) -> Self {
+        self.gi_upsampling = gi_upsampling;
+        self
+    }
+
     /// Mutably set resolution factor (clamped above 1).
     pub fn set_resolution_factor(&mut self, mut resolution_factor: u32) {
         resolution_factor = u32::max(resolution_factor, 1);
@@ -733,6 +1236,71 @@ impl RadianceCascadesConfig {
         self.interval0 = interval0;
     }
 
+    /// Mutably set emission decoding.
+    pub fn set_emission_decoding(&mut self, emission_decoding: EmissionDecoding) {
+        self.emission_decoding = emission_decoding;
+    }
+
+    /// Mutably enable or disable negative light.
+    pub fn set_negative_light(&mut self, negative_light: bool) {
+        self.negative_light = negative_light;
+    }
+
+    /// Mutably set GI intensity (clamped above 0).
+    pub fn set_gi_intensity(&mut self, gi_intensity: f32) {
+        self.gi_intensity = f32::max(gi_intensity, 0.0);
+    }
+
+    /// Mutably set GI tint.
+    pub fn set_gi_tint(&mut self, gi_tint: Color) {
+        self.gi_tint = gi_tint;
+    }
+
+    /// Mutably set ambient floor.
+    pub fn set_ambient(&mut self, ambient: Color) {
+        self.ambient = ambient;
+    }
+
+    /// Mutably set occluder surface search depth in pixel unit (clamped above 0).
+    pub fn set_surface_depth(&mut self, surface_depth: f32) {
+        self.surface_depth = f32::max(surface_depth, 0.0);
+    }
+
+    /// Mutably set occluder rim light (clamped above 0).
+    pub fn set_rim_light(&mut self, rim_light: f32) {
+        self.rim_light = f32::max(rim_light, 0.0);
+    }
+
+    /// Mutably enable or disable conservative marching.
+    pub fn set_conservative_march(&mut self, conservative_march: bool) {
+        self.conservative_march = conservative_march;
+    }
+
+    /// Mutably enable or disable bilinear distance field sampling.
+    pub fn set_filtered_dist_field(&mut self, filtered_dist_field: bool) {
+        self.filtered_dist_field = filtered_dist_field;
+    }
+
+    /// Mutably enable or disable ray jitter.
+    pub fn set_ray_jitter(&mut self, ray_jitter: bool) {
+        self.ray_jitter = ray_jitter;
+    }
+
+    /// Mutably set ray jitter seed.
+    pub fn set_jitter_seed(&mut self, jitter_seed: u32) {
+        self.jitter_seed = jitter_seed;
+    }
+
+    /// Mutably enable or disable cosine weighted irradiance.
+    pub fn set_cosine_irradiance(&mut self, cosine_irradiance: bool) {
+        self.cosine_irradiance = cosine_irradiance;
+    }
+
+    /// Mutably set GI upsampling filter.
+    pub fn set_gi_upsampling(&mut self, gi_upsampling: GiUpsampling) {
+        self.gi_upsampling = gi_upsampling;
+    }
+
     pub fn get_resolution_factor(&self) -> u32 {
         self.resolution_factor
     }
@@ -740,6 +1308,58 @@ impl RadianceCascadesConfig {
     pub fn get_interval(&self) -> f32 {
         self.interval0
     }
+
+    pub fn get_emission_decoding(&self) -> EmissionDecoding {
+        self.emission_decoding
+    }
+
+    pub fn get_negative_light(&self) -> bool {
+        self.negative_light
+    }
+
+    pub fn get_gi_intensity(&self) -> f32 {
+        self.gi_intensity
+    }
+
+    pub fn get_gi_tint(&self) -> Color {
+        self.gi_tint
+    }
+
+    pub fn get_ambient(&self) -> Color {
+        self.ambient
+    }
+
+    pub fn get_surface_depth(&self) -> f32 {
+        self.surface_depth
+    }
+
+    pub fn get_rim_light(&self) -> f32 {
+        self.rim_light
+    }
+
+    pub fn get_conservative_march(&self) -> bool {
+        self.conservative_march
+    }
+
+    pub fn get_filtered_dist_field(&self) -> bool {
+        self.filtered_dist_field
+    }
+
+    pub fn get_ray_jitter(&self) -> bool {
+        self.ray_jitter
+    }
+
+    pub fn get_jitter_seed(&self) -> u32 {
+        self.jitter_seed
+    }
+
+    pub fn get_cosine_irradiance(&self) -> bool {
+        self.cosine_irradiance
+    }
+
+    pub fn get_gi_upsampling(&self) -> GiUpsampling {
+        self.gi_upsampling
+    }
 }
 
 impl Default for RadianceCascadesConfig {
@@ -747,10 +1367,63 @@ impl Default for RadianceCascadesConfig {
         Self {
             resolution_factor: 1,
             interval0: 2.0,
+            emission_decoding: EmissionDecoding::default(),
+            negative_light: false,
+            gi_intensity: 1.0,
+            gi_tint: Color::WHITE,
+            ambient: Color::BLACK,
+            surface_depth: 0.0,
+            rim_light: 0.0,
+            conservative_march: false,
+            filtered_dist_field: false,
+            ray_jitter: false,
+            jitter_seed: 0,
+            cosine_irradiance: false,
+            gi_upsampling: GiUpsampling::default(),
+        }
+    }
+}
+
+/// How colors of the main pass hit by rays are turned into emitted light.
+///
+/// [`Mask2dProperties`](crate::mask2d::Mask2dProperties) and mask prepass emission are added
+/// on top regardless.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmissionDecoding {
+    /// Channels above `threshold` emit `(c - threshold)^curve * scale`, so that regular
+    /// colors up to `threshold` only reflect light.
+    Threshold {
+        threshold: f32,
+        scale: f32,
+        curve: f32,
+    },
+    /// Main pass colors never emit, light only comes from the mask prepass and lights.
+    EmissionBufferOnly,
+}
+
+impl Default for EmissionDecoding {
+    fn default() -> Self {
+        Self::Threshold {
+            threshold: 1.0,
+            scale: 1.0,
+            curve: 1.0,
         }
     }
 }
 
+/// Filter upsampling the probe grid, one probe every `2^resolution_factor` pixels, to every pixel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GiUpsampling {
+    /// Cheapest, shows the probe grid as blocky gradients at low resolutions.
+    #[default]
+    Bilinear,
+    /// Smooth cubic B-spline, slightly blurrier.
+    Bicubic,
+    /// Bilinear, ignoring probes whose distance to occluders differs from the pixel's,
+    /// which keeps light from bleeding through walls.
+    JointBilateral,
+}
+
 #[derive(Component, Debug, Clone, Copy)]
 pub struct RadianceCascadesCount(usize);
 
@@ -761,20 +1434,142 @@ struct Probe {
     pub start: f32,
     /// Range of ray.
     pub range: f32,
+    pub emission_threshold: f32,
+    pub emission_scale: f32,
+    pub emission_curve: f32,
+    /// `EMISSION_*` bits.
+    pub emission_flags: u32,
+    /// Whether rays march conservatively, see [`RadianceCascadesConfig::with_conservative_march`].
+    pub conservative: u32,
+    /// Whether the distance field is bilinearly filtered, see
+    /// [`RadianceCascadesConfig::with_filtered_dist_field`].
+    pub filtered: u32,
+    /// Whether rays are jittered, see [`RadianceCascadesConfig::with_ray_jitter`].
+    pub jitter: u32,
+    pub jitter_seed: u32,
+    /// Frame count animating the jitter.
+    pub frame_index: u32,
+    /// Whether the mipmap pass integrates directional irradiance, see
+    /// [`RadianceCascadesConfig::with_cosine_irradiance`].
+    pub cosine: u32,
+    /// Share of the circle covered by each direction of the probe.
+    pub direction_weight: f32,
+    /// Weight of each direction in the first order harmonic of the probe.
+    pub harmonic_weight: f32,
+}
+
+impl Probe {
+    const EMISSION_MAIN: u32 = 1 << 0;
+    const EMISSION_NEGATIVE: u32 = 1 << 1;
+
+    /// Probe of the given cascade with the config's settings, frame index left at zero.
+    fn cascade(config: &RadianceCascadesConfig, cascade: usize) -> Self {
+        let width = 1 << (cascade as u32 + config.resolution_factor);
+
+        Self {
+            width,
+            start: config.interval0 * (1.0 - f32::powi(4.0, cascade as i32)) / -3.0,
+            range: config.interval0 * f32::powi(4.0, cascade as i32),
+            direction_weight: direction_weight(width),
+            harmonic_weight: harmonic_weight(width),
+            ..Self::from(config)
+        }
+    }
+}
+
+impl From<&RadianceCascadesConfig> for Probe {
+    /// Probe with the config's settings, cascade parameters and frame index are left at zero.
+    fn from(config: &RadianceCascadesConfig) -> Self {
+        let mut emission_flags = 0;
+        if config.negative_light {
+            emission_flags |= Self::EMISSION_NEGATIVE;
+        }
+
+        let (emission_threshold, emission_scale, emission_curve) = match config.emission_decoding {
+            EmissionDecoding::Threshold {
+                threshold,
+                scale,
+                curve,
+            } => {
+                emission_flags |= Self::EMISSION_MAIN;
+                (threshold, scale, curve)
+            }
+            EmissionDecoding::EmissionBufferOnly => (0.0, 0.0, 1.0),
+        };
+
+        Self {
+            width: 0,
+            start: 0.0,
+            range: 0.0,
+            emission_threshold,
+            emission_scale,
+            emission_curve,
+            emission_flags,
+            conservative: config.conservative_march as u32,
+            filtered: config.filtered_dist_field as u32,
+            jitter: config.ray_jitter as u32,
+            jitter_seed: config.jitter_seed,
+            frame_index: 0,
+            cosine: config.cosine_irradiance as u32,
+            direction_weight: 0.0,
+            harmonic_weight: 0.0,
+        }
+    }
+}
+
+/// Weight of each of the `width * width` directions of a probe when integrating it,
+/// so that irradiance does not depend on the angular resolution.
+fn direction_weight(width: u32) -> f32 {
+    1.0 / (width * width) as f32
+}
+
+/// Weight of each of the `width * width` directions of a probe when integrating its first order
+/// harmonic, twice the direction weight as `cos²` and `sin²` average to one half.
+fn harmonic_weight(width: u32) -> f32 {
+    direction_weight(width) * 2.0
+}
+
+/// Scaling of the GI in the apply pass.
+#[derive(ShaderType, Debug, Clone, Copy)]
+struct GiSettings {
+    /// Tint multiplied by intensity and exposure.
+    pub tint: Vec3,
+    /// Ambient floor multiplied by exposure.
+    pub ambient: Vec3,
+    pub surface_depth: f32,
+    pub rim_light: f32,
+    /// Whether surfaces are lit with cosine weighted irradiance.
+    pub cosine: u32,
+    /// [`GiUpsampling`] index.
+    pub upsampling: u32,
 }
 
 #[derive(Component)]
 pub struct RadianceCascadesBuffer {
     probe_buffers: DynamicUniformBuffer<Probe>,
     probe_buffer_offsets: Vec<u32>,
+    gi_buffer: UniformBuffer<GiSettings>,
 }
 
+/// Per view irradiance of the previous frame.
+#[derive(Resource, Default)]
+pub struct RadianceCascadesHistory(EntityHashMap<CachedTexture>);
+
 #[derive(Component)]
 pub struct RadianceCascadesTextures {
     pub dist_field_texture: CachedTexture,
     pub radiance_cascades_texture0: CachedTexture,
     pub radiance_cascades_texture1: CachedTexture,
+    /// Irradiance of the probes with a full mip chain, each level a 2x2 average of the previous
+    /// one. Blurrier levels suit rough or glossy sampling in user shaders.
     pub radiance_mipmap_texture: CachedTexture,
+    /// Single level views of [`Self::radiance_mipmap_texture`].
+    pub radiance_mipmap_levels: Vec<TextureView>,
+    /// First order harmonic of the mipmap's luminance, written with cosine irradiance only.
+    pub radiance_direction_texture: CachedTexture,
+    /// Irradiance of the previous frame, bounced off occluders, see
+    /// [`Mask2dProperties::absorption`][crate::mask2d::Mask2dProperties::absorption].
+    pub radiance_history_texture: CachedTexture,
     is_texture0: bool,
 }
 
@@ -796,4 +1591,154 @@ pub struct RadianceCascadesBindGroups {
     radiance_cascades_01_bind_group: BindGroup,
     radiance_cascades_10_bind_group: BindGroup,
     radiance_cascades_mipmap_bind_group: BindGroup,
+    /// Downsampling into each mip level after the first, from the level before.
+    radiance_mipmap_blit_bind_groups: Vec<BindGroup>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct RadianceCascadesApplyPipelineKey {
+    /// Format of the view target, HDR or not.
+    texture_format: TextureFormat,
+}
+
+/// Apply pipeline matching the view target.
+#[derive(Component)]
+pub struct RadianceCascadesApplyPipelineId(CachedRenderPipelineId);
+
+/// These tests check the values uploaded in [`Probe`] against the normalization the mipmap and
+/// apply passes expect, integrating with a Rust counterpart of their math. The WGSL itself is not
+/// covered.
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{PI, TAU};
+
+    use super::*;
+
+    /// Angle of a ray of the probe, `jitter` in ray spacings, like `ray_angle` in
+    /// `radiance_probe.wgsl`.
+    fn ray_angle(probe: &Probe, ray_index: u32, jitter: f32) -> f32 {
+        (ray_index as f32 + 0.5 + jitter) / (probe.width * probe.width) as f32 * TAU
+    }
+
+    /// Mean and first order harmonic of a probe whose rays, rotated by `jitter`, see
+    /// `radiance(direction)`, weighted with the probe's uploaded weights like `probe_radiance`
+    /// in `radiance_cascades_mipmap.wgsl`.
+    fn integrate_probe(probe: &Probe, jitter: f32, radiance: impl Fn(Vec2) -> f32) -> (f32, Vec2) {
+        let (mut mean, mut direction) = (0.0, Vec2::ZERO);
+        for ray_index in 0..probe.width * probe.width {
+            let ray_dir = Vec2::from_angle(ray_angle(probe, ray_index, jitter));
+            mean += radiance(ray_dir);
+            direction += ray_dir * radiance(ray_dir);
+        }
+
+        (
+            mean * probe.direction_weight,
+            direction * probe.harmonic_weight,
+        )
+    }
+
+    /// Like `surface_radiance` in `radiance_cascades_apply.wgsl`.
+    fn cosine_irradiance(mean: f32, direction: Vec2, normal: Vec2) -> f32 {
+        mean * f32::max(
+            1.0 + PI * 0.25 * direction.dot(normal) / mean.max(4.88e-04),
+            0.0,
+        )
+    }
+
+    /// Probes of every cascade for a range of resolution factors, with and without jitter.
+    fn probes() -> impl Iterator<Item = Probe> {
+        (1..=5).flat_map(|resolution_factor| {
+            [false, true].into_iter().flat_map(move |ray_jitter| {
+                let config = RadianceCascadesConfig::default()
+                    .with_resolution_factor(resolution_factor)
+                    .with_ray_jitter(ray_jitter);
+                (0..3).map(move |cascade| Probe::cascade(&config, cascade))
+            })
+        })
+    }
+
+    const JITTERS: [f32; 4] = [-0.5, -0.2, 0.0, 0.37];
+
+    #[test]
+    fn uploaded_weights_normalize_the_probe_integrals() {
+        for probe in probes() {
+            let ray_count = (probe.width * probe.width) as f32;
+            assert_eq!(probe.direction_weight * ray_count, 1.0);
+
+            // A linear environment is recovered exactly by its first order harmonic
+            let gradient = Vec2::new(0.3, -0.8);
+            for jitter in JITTERS {
+                let (mean, direction) =
+                    integrate_probe(&probe, jitter, |ray_dir| 1.0 + gradient.dot(ray_dir));
+                assert!(
+                    (mean - 1.0).abs() < 1e-4,
+                    "width {} gives {mean}",
+                    probe.width
+                );
+                assert!(
+                    direction.distance(gradient) < 1e-4,
+                    "width {} jitter {jitter} gives {direction}",
+                    probe.width
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_environment_is_identical_across_settings() {
+        for probe in probes() {
+            for jitter in JITTERS {
+                let (mean, direction) = integrate_probe(&probe, jitter, |_| 3.0);
+                assert!(
+                    (mean - 3.0).abs() < 1e-4,
+                    "width {} integrates to {mean}",
+                    probe.width
+                );
+
+                for normal in [Vec2::X, Vec2::NEG_Y, Vec2::from_angle(1.0)] {
+                    let irradiance = cosine_irradiance(mean, direction, normal);
+                    assert!(
+                        (irradiance - 3.0).abs() < 1e-3,
+                        "width {} facing {normal} receives {irradiance}",
+                        probe.width
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cosine_irradiance_of_half_lit_environment() {
+        // Light only comes from +x, halved clamped cosine integrals are 1 facing it and 0 away
+        for probe in probes() {
+            let (mean, direction) =
+                integrate_probe(&probe, 0.0, |ray_dir| (ray_dir.x > 0.0) as u32 as f32);
+            assert!((mean - 0.5).abs() < 1e-4);
+
+            let facing = cosine_irradiance(mean, direction, Vec2::X);
+            let away = cosine_irradiance(mean, direction, Vec2::NEG_X);
+            assert!(
+                (facing - 1.0).abs() < 0.06,
+                "facing the light receives {facing}"
+            );
+            assert!(away.abs() < 1e-4, "facing away receives {away}");
+        }
+    }
+
+    #[test]
+    fn jittered_harmonic_follows_the_rotated_rays() {
+        // A single lit ray, the harmonic points along it wherever the jitter rotates it
+        let probe = Probe::cascade(&RadianceCascadesConfig::default(), 1);
+        for jitter in JITTERS {
+            let lit = Vec2::from_angle(ray_angle(&probe, 5, jitter));
+            let (mean, direction) = integrate_probe(&probe, jitter, |ray_dir| {
+                (ray_dir.dot(lit) > 0.9999) as u32 as f32
+            });
+            assert!((mean - probe.direction_weight).abs() < 1e-6);
+            assert!(
+                direction.normalize().dot(lit) > 0.9999,
+                "jitter {jitter} points the harmonic at {direction}"
+            );
+        }
+    }
 }